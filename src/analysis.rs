@@ -28,7 +28,7 @@ pub struct Analysis {
 }
 
 impl Analysis {
-    pub async fn start<P: Provider>(
+    pub async fn start<P: Provider + ?Sized>(
         provider: Arc<P>,
         meta_context: MetaContext,
         contexts: HashMap<ContextID, Arc<Context>>
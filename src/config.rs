@@ -5,6 +5,7 @@ use std::path::Path;
 use std::fs;
 use std::io::Write;
 use std::env;
+use std::str::FromStr;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 enum LlmProvider {
@@ -21,13 +22,96 @@ pub struct LlmConfig {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DevConfig {
+    /// Directory for debug.log and the LLM cache, the working directory when unset
+    #[serde(default = "get_default_debug_dir")]
     pub debug_dir: String,
 }
 
+impl Default for DevConfig {
+    fn default() -> Self {
+        DevConfig {
+            debug_dir: get_default_debug_dir(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ProviderType {
+    Yaml,
+    Json,
+    Sqlite,
+    Void,
+}
+
+impl FromStr for ProviderType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "yaml" => Ok(ProviderType::Yaml),
+            "json" => Ok(ProviderType::Json),
+            "sqlite" => Ok(ProviderType::Sqlite),
+            "void" => Ok(ProviderType::Void),
+            _ => Err(format!("Unknown provider: {}", value)),
+        }
+    }
+}
+
+impl ProviderType {
+    pub fn default_path(&self) -> String {
+        match self {
+            ProviderType::Yaml => String::from("provider.yaml"),
+            ProviderType::Json => String::from("provider.json"),
+            ProviderType::Sqlite => String::from("provider.db"),
+            ProviderType::Void => String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProviderConfig {
+    pub provider_type: ProviderType,
+    pub path: String,
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        ProviderConfig {
+            provider_type: ProviderType::Yaml,
+            path: ProviderType::Yaml.default_path(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    pub similarity_threshold: f64,
+    #[serde(default = "default_origin_similarity_threshold")]
+    pub origin_similarity_threshold: f64,
+}
+
+fn default_origin_similarity_threshold() -> f64 {
+    0.5
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        ProfileConfig {
+            similarity_threshold: 0.8,
+            origin_similarity_threshold: default_origin_similarity_threshold(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub llm: LlmConfig,
+    #[serde(default)]
     pub dev: DevConfig,
+    #[serde(default)]
+    pub provider: ProviderConfig,
+    #[serde(default)]
+    pub profile: ProfileConfig,
 }
 
 fn get_default_debug_dir() -> String {
@@ -45,9 +129,9 @@ impl Config {
                 llm_provider: LlmProvider::OpenAI,
                 max_concurrency: 1,
             },
-            dev: DevConfig {
-                debug_dir: get_default_debug_dir(),
-            }
+            dev: DevConfig::default(),
+            provider: ProviderConfig::default(),
+            profile: ProfileConfig::default(),
         }
     }
 
@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+use csv::ReaderBuilder;
+use xmltree::{Element, XMLNode};
+
+use crate::prelude::*;
+use crate::document_node::escape_field_name;
+
+/// Name of the element wrapping a delimited document
+pub const CSV_ROOT_ELEMENT: &str = "table";
+
+/// Name of the element holding a single record
+pub const CSV_ROW_ELEMENT: &str = "row";
+
+/// Delimiters tried when sniffing, in order of preference
+pub const CSV_DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+
+/// Number of records inspected when sniffing the delimiter
+const SNIFF_RECORD_COUNT: usize = 20;
+
+/// Header cells longer than this read as data rather than column names
+const HEADER_MAX_LENGTH: usize = 64;
+
+/// Returns the delimiter that splits every leading record into the same
+/// number of fields, preferring the one yielding the most fields. A single
+/// delimiter per row is only trusted with more than two rows, and the first
+/// row has to read like a header. Returns `None` if no delimiter qualifies,
+/// so that delimited-looking prose and logs stay plain text.
+pub fn sniff_delimiter(data: &str) -> Option<u8> {
+    let mut best: Option<(usize, u8)> = None;
+
+    for delimiter in CSV_DELIMITERS.iter() {
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(*delimiter)
+            .from_reader(data.as_bytes());
+
+        let mut records: Vec<csv::StringRecord> = Vec::new();
+
+        for record in reader.records().take(SNIFF_RECORD_COUNT) {
+            match record {
+                Ok(record) => records.push(record),
+                Err(_) => {
+                    records.clear();
+                    break;
+                }
+            }
+        }
+
+        if records.len() < 2 {
+            continue;
+        }
+
+        let field_count = records[0].len();
+
+        if field_count < 2 || records.iter().any(|record| record.len() != field_count) {
+            continue;
+        }
+
+        if field_count < 3 && records.len() <= 2 {
+            continue;
+        }
+
+        if !is_header_like(&records[0]) {
+            continue;
+        }
+
+        // Ties keep the delimiter listed first
+        if best.is_none_or(|(best_count, _)| field_count > best_count) {
+            best = Some((field_count, *delimiter));
+        }
+    }
+
+    best.map(|(_, delimiter)| delimiter)
+}
+
+/// Header cells are short, distinct and do not start with a digit, which
+/// rules out rows of numbers, timestamps and sentences
+fn is_header_like(record: &csv::StringRecord) -> bool {
+    let mut seen: HashSet<&str> = HashSet::new();
+
+    record.iter().map(|cell| cell.trim()).all(|cell| {
+        !cell.is_empty()
+            && cell.chars().count() <= HEADER_MAX_LENGTH
+            && !cell.starts_with(|c: char| c.is_ascii_digit())
+            && seen.insert(cell)
+    })
+}
+
+/// Maps a delimited document onto the element tree used by `DocumentNode`.
+/// Each record becomes a row element under the table root, with one field
+/// per column named after the header row.
+pub fn csv_to_element(data: &str, delimiter: u8) -> Result<Element, Errors> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(data.as_bytes());
+
+    let headers = reader.headers().map_err(|err| {
+        log::error!("Could not read CSV header row: {}", err);
+        Errors::CsvParseError
+    })?;
+    let column_names = get_column_names(headers.iter());
+
+    let mut root = Element::new(CSV_ROOT_ELEMENT);
+
+    for record in reader.records() {
+        let record = record.map_err(|err| {
+            log::error!("Could not read CSV record: {}", err);
+            Errors::CsvParseError
+        })?;
+
+        let mut row = Element::new(CSV_ROW_ELEMENT);
+
+        for (index, value) in record.iter().enumerate() {
+            let column_name = column_names
+                .get(index)
+                .cloned()
+                .unwrap_or_else(|| get_default_column_name(index));
+
+            row.attributes.insert(column_name, value.trim().to_string());
+        }
+
+        root.children.push(XMLNode::Element(row));
+    }
+
+    Ok(root)
+}
+
+/// Names columns after the trimmed header cells, falling back to the column
+/// position for blank headers, escaping names that clash with `tag` or
+/// `namespace` and suffixing repeated names
+fn get_column_names<'a>(headers: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut seen: HashSet<String> = HashSet::new();
+
+    headers
+        .enumerate()
+        .map(|(index, header)| {
+            let header = header.trim();
+            let base = if header.is_empty() {
+                get_default_column_name(index)
+            } else {
+                escape_field_name(header)
+            };
+
+            let mut name = base.clone();
+            let mut suffix = 2;
+
+            while !seen.insert(name.clone()) {
+                name = format!("{}_{}", base, suffix);
+                suffix += 1;
+            }
+
+            name
+        })
+        .collect()
+}
+
+fn get_default_column_name(index: usize) -> String {
+    format!("column_{}", index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_node::DocumentNode;
+
+    #[test]
+    fn sniffs_delimited_tables() {
+        assert_eq!(sniff_delimiter("name,age\nAda,36\nAlan,41\n"), Some(b','));
+        assert_eq!(sniff_delimiter("name\tage\tcity\nAda\t36\tLondon\n"), Some(b'\t'));
+        assert_eq!(sniff_delimiter("a;b;c\n1;2;3\n4;5;6\n"), Some(b';'));
+    }
+
+    #[test]
+    fn prefers_the_delimiter_with_most_fields() {
+        assert_eq!(sniff_delimiter("a|b|c,d\n1|2|3,4\n5|6|7,8\n"), Some(b'|'));
+    }
+
+    #[test]
+    fn leaves_logs_and_prose_as_text() {
+        assert_eq!(sniff_delimiter("2024-01-01 ERROR foo, bar\n2024-01-02 INFO baz, qux\n"), None);
+        assert_eq!(sniff_delimiter("2024-01-01 ERROR foo, bar\n2024-01-02 INFO baz, qux\n2024-01-03 INFO a, b\n"), None);
+        assert_eq!(sniff_delimiter("Hello, world\nGoodbye, moon\n"), None);
+        assert_eq!(sniff_delimiter("just one line, really"), None);
+    }
+
+    #[test]
+    fn requires_consistent_field_counts() {
+        assert_eq!(sniff_delimiter("a,b,c\n1,2\n3,4,5\n"), None);
+    }
+
+    #[test]
+    fn requires_a_header_like_first_row() {
+        assert_eq!(sniff_delimiter("1,2,3\n4,5,6\n7,8,9\n"), None);
+        assert_eq!(sniff_delimiter("a,a,b\n1,2,3\n4,5,6\n"), None);
+        assert_eq!(sniff_delimiter("a,,b\n1,2,3\n4,5,6\n"), None);
+    }
+
+    #[test]
+    fn names_rows_after_the_header() {
+        let element = csv_to_element("name, age\nAda, 36\n", b',').unwrap();
+        let XMLNode::Element(row) = &element.children[0] else {
+            panic!("Expected a row element");
+        };
+
+        assert_eq!(row.name, CSV_ROW_ELEMENT);
+        assert_eq!(row.attributes.get("name").map(String::as_str), Some("Ada"));
+        assert_eq!(row.attributes.get("age").map(String::as_str), Some("36"));
+    }
+
+    #[test]
+    fn keeps_columns_named_like_structural_fields() {
+        let element = csv_to_element("name,tag,namespace,owner\nfoo,v1,ns,ada\n", b',').unwrap();
+        let XMLNode::Element(row) = &element.children[0] else {
+            panic!("Expected a row element");
+        };
+
+        let fields = DocumentNode::new(XMLNode::Element(row.clone())).get_fields();
+
+        assert_eq!(fields["tag"], CSV_ROW_ELEMENT);
+        assert_eq!(fields["_tag"], "v1");
+        assert_eq!(fields["_namespace"], "ns");
+        assert_eq!(fields["owner"], "ada");
+        assert!(!fields.contains_key("namespace"));
+    }
+}
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 
 use crate::prelude::*;
 use crate::transformation::HashTransformation;
+use crate::provenance::Provenance;
 
 pub type DataNodeFields = HashMap<String, String>;
 
@@ -13,6 +14,10 @@ pub struct DataNode {
     pub lineage: Lineage,
     pub fields: DataNodeFields,
     pub description: String,
+    /// Where the node was found in the original document. Not part of the
+    /// hash, so moving content around doesn't change its lineage.
+    #[serde(default)]
+    pub provenance: Provenance,
 }
 
 impl DataNode {
@@ -21,6 +26,7 @@ impl DataNode {
         fields: DataNodeFields,
         description: String,
         parent_lineage: &Lineage,
+        provenance: Provenance,
     ) -> Self {
         let hash: Hash = hash_transformation.transform(fields.clone());
         let lineage = parent_lineage.with_hash(hash.clone());
@@ -31,6 +37,7 @@ impl DataNode {
             fields,
             lineage,
             description,
+            provenance,
         }
     }
 
@@ -4,15 +4,25 @@ use html5ever::parse_document;
 use html5ever::tendril::TendrilSink;
 use markup5ever_rcdom::{Handle, NodeData, RcDom};
 use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 use std::sync::Arc;
+use serde_json::Value;
 
 use crate::prelude::*;
 use crate::data_node::{DataNode};
 use crate::document_node::{DocumentNode};
 use crate::provider::Provider;
-use crate::profile::Profile;
-use crate::transformation::XMLElementTransformation;
+use crate::profile::{Profile, hash_features};
+use crate::transformation::{XMLElementTransformation, HashTransformation};
 use crate::hash::{Hash};
+use crate::json_document::json_to_element;
+use crate::xml_document::{parse_xml, parse_xhtml, get_qualified_name};
+use crate::text_document::text_to_element;
+use crate::csv_document::{csv_to_element, sniff_delimiter};
+use crate::encoding::decode_bytes;
+use crate::provenance::locate_sources;
+use crate::page_metadata::extract_page_metadata;
+use crate::structured_data::{StructuredData, extract_structured_data};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum DocumentType {
@@ -20,12 +30,47 @@ pub enum DocumentType {
     PLAIN_TEXT,
     XML,
     HTML,
+    CSV,
+    /// Only produced as output
+    YAML,
+    /// Only produced as output, into a database file
+    SQLITE,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl FromStr for DocumentType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "json" => Ok(DocumentType::JSON),
+            "xml" => Ok(DocumentType::XML),
+            "html" => Ok(DocumentType::HTML),
+            "csv" | "tsv" => Ok(DocumentType::CSV),
+            "text" | "plain_text" => Ok(DocumentType::PLAIN_TEXT),
+            "yaml" | "yml" | "sqlite" => {
+                Err(format!("{} documents are only produced, not read", value))
+            },
+            _ => Err(format!("Unknown document type: {}", value)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DocumentMetadata {
     pub origin: Option<String>,
     pub date: Option<String>,
+    /// Encoding the document was decoded from before being held as UTF-8
+    pub encoding: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub canonical_url: Option<String>,
+    pub base_url: Option<String>,
+    /// OpenGraph properties such as `og:title`, keyed by property
+    pub open_graph: HashMap<String, String>,
+    /// Twitter card properties such as `twitter:card`, keyed by name
+    pub twitter_card: HashMap<String, String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -36,22 +81,67 @@ pub struct Document {
 }
 
 impl Document {
+    /// Builds a document from raw input, detecting its encoding unless one is
+    /// given in the options and transcoding it to UTF-8
+    pub fn from_bytes(
+        value: &[u8],
+        options: &Option<Options>,
+    ) -> Result<Self, Errors> {
+        let label = options.as_ref().and_then(|opts| opts.encoding.clone());
+        let (text, encoding) = decode_bytes(value, label.as_deref());
+
+        log::info!("Decoded document from {}", encoding.name());
+
+        let mut document = Document::from_string(text, options)?;
+        document.metadata.encoding = Some(encoding.name().to_string());
+
+        Ok(document)
+    }
+
     pub fn from_string(
         value: String,
         options: &Option<Options>,
     ) -> Result<Self, Errors> {
+        // Parsers get the same text the type was sniffed from
+        let value = match value.strip_prefix('\u{feff}') {
+            Some(stripped) => stripped.to_string(),
+            None => value,
+        };
+
         if value.trim().is_empty() {
             return Err(Errors::DocumentNotProvided);
         }
 
-        Ok(Document {
-            document_type: DocumentType::PLAIN_TEXT,
+        let document_type = match options.as_ref().and_then(|opts| opts.document_type.clone()) {
+            Some(document_type) => {
+                log::info!("Document type set by options: {:?}", document_type);
+                document_type
+            },
+            None => {
+                let document_type = sniff_document_type(&value);
+                log::info!("Sniffed document type: {:?}", document_type);
+                document_type
+            }
+        };
+
+        let mut document = Document {
+            document_type,
             metadata: DocumentMetadata {
                 origin: options.as_ref().and_then(|opts| opts.origin.clone()),
                 date: options.as_ref().and_then(|opts| opts.date.clone()),
+                encoding: Some(String::from("UTF-8")),
+                ..DocumentMetadata::default()
             },
             data: value,
-        })
+        };
+
+        if document.document_type == DocumentType::HTML {
+            if let Some(dom) = document.to_dom() {
+                extract_page_metadata(&dom, &mut document.metadata);
+            }
+        }
+
+        Ok(document)
     }
 
     pub fn to_string(self) -> String {
@@ -61,76 +151,166 @@ impl Document {
     pub fn get_document_node(&self) -> Result<DocumentNode, Errors> {
         log::trace!("In document/get_document_node");
 
-        if let Some(dom) = self.to_dom() {
-
-            let mut xml = String::from("");
-            walk(&mut xml, &dom.document, 0);
-
-
+        self.get_document_node_with_structured_data()
+            .map(|(document_node, _)| document_node)
+    }
 
-            let mut reader = std::io::Cursor::new(xml);
+    /// The document node along with any structured data, parsing an HTML
+    /// document only once for both
+    pub fn get_document_node_with_structured_data(
+        &self
+    ) -> Result<(DocumentNode, Vec<StructuredData>), Errors> {
+        log::trace!("In document/get_document_node_with_structured_data");
+
+        let mut structured_data: Vec<StructuredData> = Vec::new();
+
+        let element = match self.document_type {
+            DocumentType::JSON => self.to_json_element().ok_or(Errors::JsonParseError)?,
+            DocumentType::XML => parse_xml(&self.data)?,
+            DocumentType::PLAIN_TEXT => text_to_element(&self.data),
+            DocumentType::CSV => self.to_csv_element()?,
+            DocumentType::HTML => {
+                let dom = self.to_dom().ok_or_else(|| {
+                    log::error!("Could not parse HTML document");
+                    Errors::UnexpectedDocumentType
+                })?;
+
+                structured_data = extract_structured_data(&dom);
+
+                dom_to_element(&dom)?
+            },
+            DocumentType::YAML | DocumentType::SQLITE => {
+                log::error!("{:?} documents can't be parsed, only produced", self.document_type);
+                return Err(Errors::UnexpectedDocumentType);
+            },
+        };
 
-            match Element::parse(reader) {
-                Ok(element) => Ok(DocumentNode::new(xmltree::XMLNode::Element(element))),
-                Err(e) => {
-                    log::error!("Could not parse XML: {}", e);
+        let provenance = locate_sources(&element, &self.data, &self.document_type);
+        let document_node = DocumentNode::with_provenance(XMLNode::Element(element), Arc::new(provenance));
 
-                    Err(Errors::XmlParseError)
-                }
-            }
-        } else {
-            unimplemented!()
-        }
+        Ok((document_node, structured_data))
     }
 
-    pub async fn perform_analysis<P: Provider>(
+    pub async fn perform_analysis<P: Provider + ?Sized>(
         &mut self,
         provider: Arc<P>
     ) -> Result<Profile, Errors> {
         log::trace!("In document/perform_analysis");
 
-        if let Some(dom) = self.to_dom() {
-            log::info!("It seems to be possible to parse this document as XML");
-
-            self.document_type = DocumentType::XML;
+        if let Some(feature_paths) = self.get_feature_paths() {
+            log::info!("Analysing document as {:?}", self.document_type);
 
-            let mut features: HashSet<String> = HashSet::new();
+            let features: HashSet<Hash> = hash_features(&feature_paths);
 
-            get_xml_features(
-                &dom.document,
-                &mut String::from(""),
-                &mut features,
-            );
-
-            let features: HashSet<Hash> = features.iter().map(|feature| {
-                let mut hash = Hash::new();
-                hash.push(feature).finalize().clear_items();
-                hash.clone()
-            }).collect();
-
-            if let Some(profile) = provider.get_profile(&features).await? {
+            if let Some(mut profile) = provider.get_profile(&features, &self.metadata.origin).await? {
                 log::info!("Found a profile");
 
+                let mut is_updated = false;
+
                 if profile.xml_element_transformation.is_none() {
-                    log::info!("Profile provided but xml transformation missing");
-                    unimplemented!();
+                    log::info!("Profile provided but xml transformation missing, using default");
+                    profile.xml_element_transformation = Some(self.get_default_xml_element_transformation());
+                    is_updated = true;
                 }
 
                 if profile.hash_transformation.is_none() {
-                    log::info!("Profile provided but hash transformation is missing");
-                    unimplemented!();
+                    log::info!("Profile provided but hash transformation is missing, using default");
+                    profile.hash_transformation = Some(HashTransformation::default());
+                    is_updated = true;
+                }
+
+                if is_updated {
+                    provider.save_profile(&profile).await?;
                 }
 
                 Ok(profile)
             } else {
                 log::info!("Profile not provided, we will create a new one");
-                unimplemented!();
+
+                let description = match &self.metadata.origin {
+                    Some(origin) => format!("Generated profile for {}", origin),
+                    None => String::from("Generated profile"),
+                };
+
+                let profile = Profile::from_feature_paths(
+                    &feature_paths,
+                    description,
+                    &self.metadata.origin,
+                    self.get_default_xml_element_transformation(),
+                );
+
+                provider.save_profile(&profile).await?;
+
+                log::info!("Saved new profile: {}", profile.id.to_string());
+
+                Ok(profile)
             }
         } else {
              Err(Errors::UnexpectedDocumentType)
         }
     }
 
+    /// Pulls JSON-LD, microdata and RDFa items out of the raw HTML, before
+    /// profile transformations get a chance to remove `script` elements
+    pub fn get_structured_data(&self) -> Vec<StructuredData> {
+        if self.document_type != DocumentType::HTML {
+            return Vec::new();
+        }
+
+        self.to_dom()
+            .map(|dom| extract_structured_data(&dom))
+            .unwrap_or_default()
+    }
+
+    /// Returns the unhashed structural features of the document, such as
+    /// `/html/body/table/tr/td.class`, if it can be parsed as XML
+    pub fn get_feature_paths(&self) -> Option<HashSet<String>> {
+        let mut features: HashSet<String> = HashSet::new();
+
+        match self.document_type {
+            DocumentType::JSON => {
+                let element = self.to_json_element()?;
+
+                get_element_features(&element, "", &mut features);
+
+                return Some(features);
+            },
+            DocumentType::XML => {
+                let element = parse_xml(&self.data).ok()?;
+
+                get_element_features(&element, "", &mut features);
+
+                return Some(features);
+            },
+            DocumentType::PLAIN_TEXT => {
+                let element = text_to_element(&self.data);
+
+                get_element_features(&element, "", &mut features);
+
+                return Some(features);
+            },
+            DocumentType::CSV => {
+                let element = self.to_csv_element().ok()?;
+
+                get_element_features(&element, "", &mut features);
+
+                return Some(features);
+            },
+            DocumentType::YAML | DocumentType::SQLITE => return None,
+            DocumentType::HTML => {}
+        }
+
+        let dom = self.to_dom()?;
+
+        get_xml_features(
+            &dom.document,
+            &mut String::from(""),
+            &mut features,
+        );
+
+        Some(features)
+    }
+
     //pub fn apply_transformations(
     //    &mut self,
     //    profile: &Profile
@@ -162,16 +342,49 @@ impl Document {
     //    }
     //}
 
-    fn to_dom(&self) -> Option<RcDom> {
-        let sanitized = self.data.replace("\n", "");
+    /// The default blacklist targets HTML; JSON keys and XML elements such as
+    /// `link` or `size` are data and must survive, as must every line of text
+    fn get_default_xml_element_transformation(&self) -> XMLElementTransformation {
+        match self.document_type {
+            DocumentType::HTML => XMLElementTransformation::default(),
+            _ => XMLElementTransformation::passthrough(),
+        }
+    }
+
+    fn to_json_element(&self) -> Option<Element> {
+        let value: Value = serde_json::from_str(&self.data).map_err(|err| {
+            log::error!("Could not parse JSON: {}", err);
+        }).ok()?;
+
+        Some(json_to_element(&value))
+    }
+
+
+    /// Commas are assumed when the delimiter can't be sniffed, for instance
+    /// when the type was forced on a file with a single column
+    fn to_csv_element(&self) -> Result<Element, Errors> {
+        let delimiter = sniff_delimiter(&self.data).unwrap_or(b',');
 
+        csv_to_element(&self.data, delimiter)
+    }
+
+    /// Parses the document as HTML. The DOM isn't kept, as it can't be sent
+    /// between threads and documents are held across awaits.
+    fn to_dom(&self) -> Option<RcDom> {
         parse_document(RcDom::default(), Default::default())
             .from_utf8()
-            .read_from(&mut sanitized.as_bytes())
+            .read_from(&mut self.data.as_bytes())
             .ok()
     }
 }
 
+fn dom_to_element(dom: &RcDom) -> Result<Element, Errors> {
+    let mut xml = String::from("");
+    walk(&mut xml, &dom.document, false);
+
+    parse_xhtml(&xml)
+}
+
 fn get_xml_features(
     node: &Handle,
     path: &mut String,
@@ -183,7 +396,7 @@ fn get_xml_features(
                 get_xml_features(child, path, features);
             }
         }
-        NodeData::Text { .. } => {
+        NodeData::Text { ref contents } if !contents.borrow().trim().is_empty() => {
             features.insert(format!("{}/text", path));
         }
         NodeData::Element {
@@ -206,6 +419,140 @@ fn get_xml_features(
     }
 }
 
+fn get_element_features(
+    element: &Element,
+    path: &str,
+    features: &mut HashSet<String>,
+) {
+    let new_path = format!("{}/{}", path, get_qualified_name(element));
+
+    for attr_name in element.attributes.keys() {
+        features.insert(format!("{}.{}", new_path, attr_name));
+    }
+
+    for child in element.children.iter() {
+        match child {
+            XMLNode::Element(child_element) => {
+                get_element_features(child_element, &new_path, features);
+            },
+            XMLNode::Text(_) => {
+                features.insert(format!("{}/text", new_path));
+            },
+            XMLNode::CData(_) => {
+                features.insert(format!("{}/cdata", new_path));
+            },
+            XMLNode::ProcessingInstruction(name, _) => {
+                features.insert(format!("{}/?{}", new_path, name));
+            },
+            XMLNode::Comment(_) => {}
+        }
+    }
+}
+
+/// Elements that only appear as the root of an HTML document or fragment
+const HTML_ROOT_ELEMENTS: [&str; 24] = [
+    "html", "head", "body", "meta", "title", "link", "script", "style",
+    "div", "span", "p", "a", "table", "ul", "ol", "form", "img", "br",
+    "header", "footer", "main", "section", "article", "nav",
+];
+
+/// Guesses the type of a document from its leading content. Byte order
+/// marks are skipped, JSON must parse, a doctype decides between HTML and
+/// XML, an XML declaration means XML unless the root is `html`, and other
+/// markup rooted at an HTML element means HTML. Text that a delimiter splits into
+/// consistent columns is CSV; anything else is plain text.
+pub fn sniff_document_type(value: &str) -> DocumentType {
+    let trimmed = value.trim_start_matches(['\u{feff}', '\u{fffe}']).trim_start();
+
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<Value>(trimmed).is_ok() {
+        return DocumentType::JSON;
+    }
+
+    if !trimmed.starts_with('<') {
+        return if sniff_delimiter(trimmed).is_some() {
+            DocumentType::CSV
+        } else {
+            DocumentType::PLAIN_TEXT
+        };
+    }
+
+    let has_declaration = trimmed.starts_with("<?xml");
+    let prolog = skip_markup_prolog(trimmed);
+    let lowercase_prolog = prolog.to_lowercase();
+
+    if let Some(doctype) = lowercase_prolog.strip_prefix("<!doctype") {
+        let doctype_name = doctype
+            .split(|c: char| c.is_whitespace() || c == '>' || c == '[')
+            .find(|part| !part.is_empty())
+            .unwrap_or("");
+
+        return if doctype_name == "html" {
+            DocumentType::HTML
+        } else {
+            DocumentType::XML
+        };
+    }
+
+    let root_name = get_root_element_name(prolog);
+
+    if has_declaration {
+        return if root_name.as_deref() == Some("html") {
+            DocumentType::HTML
+        } else {
+            DocumentType::XML
+        };
+    }
+
+    if root_name.as_deref().is_some_and(|name| HTML_ROOT_ELEMENTS.contains(&name)) {
+        return DocumentType::HTML;
+    }
+
+    if parse_xml(trimmed).is_ok() {
+        return DocumentType::XML;
+    }
+
+    if root_name.is_some() {
+        DocumentType::HTML
+    } else {
+        DocumentType::PLAIN_TEXT
+    }
+}
+
+/// Skips the XML declaration, processing instructions, comments and
+/// whitespace preceding the doctype or root element
+fn skip_markup_prolog(value: &str) -> &str {
+    let mut remaining = value.trim_start();
+
+    loop {
+        let end_marker = if remaining.starts_with("<?") {
+            "?>"
+        } else if remaining.starts_with("<!--") {
+            "-->"
+        } else {
+            return remaining;
+        };
+
+        match remaining.find(end_marker) {
+            Some(index) => remaining = remaining[index + end_marker.len()..].trim_start(),
+            None => return remaining,
+        }
+    }
+}
+
+fn get_root_element_name(value: &str) -> Option<String> {
+    let name: String = value.strip_prefix('<')?
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == ':' || *c == '-' || *c == '_' || *c == '.')
+        .collect();
+
+    if !name.starts_with(|c: char| c.is_alphabetic()) {
+        None
+    } else {
+        Some(name.to_lowercase())
+    }
+}
+
 //fn walk_transform(
 //    xml: &mut String,
 //    node: &Handle,
@@ -299,26 +646,31 @@ fn get_xml_features(
 //    }
 //}
 
-fn walk(xhtml: &mut String, handle: &Handle, indent: usize) {
+/// Elements whose text is rendered or consumed exactly as written
+const PRESERVE_WHITESPACE_ELEMENTS: [&str; 7] = [
+    "pre", "textarea", "listing", "plaintext", "xmp", "script", "style",
+];
+
+/// Serializes the DOM as XHTML without adding any whitespace of its own, so
+/// text nodes read back by the XML parser are exactly those produced by
+/// `normalize_whitespace`
+fn walk(xhtml: &mut String, handle: &Handle, preserve_whitespace: bool) {
     let node = handle;
-    let real_indent = " ".repeat(indent * 2);
 
     match node.data {
         NodeData::Document => {
             for child in node.children.borrow().iter() {
-                walk(xhtml, child, indent);
+                walk(xhtml, child, preserve_whitespace);
             }
         }
         NodeData::Text { ref contents } => {
-            let contents = &contents.borrow();
-            let text = format!("{}{}\n", real_indent, escape_xml(contents.trim()));
-
-            if !text.trim().is_empty() {
-                xhtml.push_str(&text);
-            }
+            let text = normalize_whitespace(&contents.borrow(), preserve_whitespace);
+            xhtml.push_str(&escape_xml(&text));
         },
         NodeData::Comment { ref contents } => {
-            log::warn!("Ignoring HTML comment: {}", contents.escape_default());
+            // Comments are kept in the tree and only become nodes for
+            // profiles that retain them
+            xhtml.push_str(&format!("<!--{}-->", escape_comment(contents)));
         },
         NodeData::Element {
             ref name,
@@ -326,8 +678,10 @@ fn walk(xhtml: &mut String, handle: &Handle, indent: usize) {
             ..
         } => {
             let tag_name = &name.local;
+            let preserve_whitespace = preserve_whitespace
+                || PRESERVE_WHITESPACE_ELEMENTS.contains(&tag_name.as_ref());
 
-            xhtml.push_str(&format!("{}<{}", real_indent, tag_name));
+            xhtml.push_str(&format!("<{}", tag_name));
 
             for attr in attrs.borrow().iter() {
                 let attr_name = &*attr.name.local.trim();
@@ -336,18 +690,60 @@ fn walk(xhtml: &mut String, handle: &Handle, indent: usize) {
                 xhtml.push_str(&format!(" {}=\"{}\"", attr_name.escape_default(), attr_value));
             }
 
-            xhtml.push_str(">\n");
+            xhtml.push('>');
 
             for child in node.children.borrow().iter() {
-                walk(xhtml, child, indent + 1);
+                walk(xhtml, child, preserve_whitespace);
             }
 
-            xhtml.push_str(&format!("{}</{}>\n", real_indent, tag_name));
+            xhtml.push_str(&format!("</{}>", tag_name));
         },
         _ => {}
     }
 }
 
+/// Applies HTML whitespace rules to a text node. Preformatted text is kept
+/// exactly as written; elsewhere each run of whitespace, line breaks
+/// included, collapses to a single space. Nothing is trimmed or dropped, so
+/// the spaces separating inline siblings survive.
+fn normalize_whitespace(text: &str, preserve_whitespace: bool) -> String {
+    if preserve_whitespace {
+        return text.to_string();
+    }
+
+    let mut normalized = String::with_capacity(text.len());
+    let mut in_whitespace = false;
+
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            if !in_whitespace {
+                normalized.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            normalized.push(c);
+            in_whitespace = false;
+        }
+    }
+
+    normalized
+}
+
+/// XML comments may not contain `--` or end with `-`
+fn escape_comment(data: &str) -> String {
+    let mut escaped = data.to_string();
+
+    while escaped.contains("--") {
+        escaped = escaped.replace("--", "- -");
+    }
+
+    if escaped.ends_with('-') {
+        escaped.push(' ');
+    }
+
+    escaped
+}
+
 fn escape_xml(data: &str) -> String {
     data.replace("&", "&amp;")
         .replace("<", "&lt;")
@@ -355,3 +751,152 @@ fn escape_xml(data: &str) -> String {
         .replace("\"", "&quot;")
         .replace("'", "&apos;")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::VoidProvider;
+
+    #[test]
+    fn sniffs_json() {
+        assert_eq!(sniff_document_type("\u{feff} {\"a\": [1, 2]}"), DocumentType::JSON);
+        assert_eq!(sniff_document_type("[1, 2, 3]"), DocumentType::JSON);
+        assert_eq!(sniff_document_type("{not json"), DocumentType::PLAIN_TEXT);
+    }
+
+    #[test]
+    fn parses_documents_starting_with_a_byte_order_mark() {
+        for text in ["\u{feff}{\"a\": 1}", "\u{feff}<root><a>1</a></root>", "\u{feff}name,age\nAda,36\nBob,40\n"] {
+            let document = Document::from_string(text.to_string(), &None).unwrap();
+
+            assert!(!document.data.starts_with('\u{feff}'));
+            assert!(document.get_document_node().is_ok(), "{:?}", document.document_type);
+            assert!(document.get_feature_paths().is_some(), "{:?}", document.document_type);
+        }
+    }
+
+    #[test]
+    fn sniffs_doctypes() {
+        assert_eq!(sniff_document_type("<!DOCTYPE html><p>hi</p>"), DocumentType::HTML);
+        assert_eq!(sniff_document_type("<!-- note --><!doctype HTML>"), DocumentType::HTML);
+        assert_eq!(sniff_document_type("<!DOCTYPE note SYSTEM \"note.dtd\"><note/>"), DocumentType::XML);
+    }
+
+    #[test]
+    fn xml_declaration_wins_over_html_like_roots() {
+        assert_eq!(sniff_document_type("<?xml version=\"1.0\"?><table><row/></table>"), DocumentType::XML);
+        assert_eq!(sniff_document_type("<?xml version=\"1.0\"?><link>https://example.com</link>"), DocumentType::XML);
+        assert_eq!(sniff_document_type("<?xml version=\"1.0\"?><html><body/></html>"), DocumentType::HTML);
+    }
+
+    #[test]
+    fn sniffs_markup_by_root_element() {
+        assert_eq!(sniff_document_type("<div><p>hi</p></div>"), DocumentType::HTML);
+        assert_eq!(sniff_document_type("<note><to>Tove</to></note>"), DocumentType::XML);
+        assert_eq!(sniff_document_type("<note><to>Tove</note>"), DocumentType::HTML);
+    }
+
+    #[test]
+    fn sniffs_csv() {
+        assert_eq!(sniff_document_type("name,age\nAda,36\nAlan,41\n"), DocumentType::CSV);
+        assert_eq!(
+            sniff_document_type("2024-01-01 ERROR foo, bar\n2024-01-02 INFO baz, qux\n"),
+            DocumentType::PLAIN_TEXT
+        );
+    }
+
+    #[test]
+    fn sniffs_plain_text() {
+        assert_eq!(sniff_document_type("Just a sentence."), DocumentType::PLAIN_TEXT);
+        assert_eq!(sniff_document_type(""), DocumentType::PLAIN_TEXT);
+    }
+
+    #[test]
+    fn parses_document_types() {
+        assert_eq!("JSON".parse::<DocumentType>(), Ok(DocumentType::JSON));
+        assert_eq!("tsv".parse::<DocumentType>(), Ok(DocumentType::CSV));
+        assert!("markdown".parse::<DocumentType>().is_err());
+        assert!("yaml".parse::<DocumentType>().is_err());
+        assert!("sqlite".parse::<DocumentType>().is_err());
+    }
+
+    fn get_html_element(html: &str) -> Element {
+        let document = Document {
+            document_type: DocumentType::HTML,
+            metadata: DocumentMetadata::default(),
+            data: html.to_string(),
+        };
+
+        dom_to_element(&document.to_dom().unwrap()).unwrap()
+    }
+
+    fn find_element<'a>(element: &'a Element, name: &str) -> Option<&'a Element> {
+        if element.name == name {
+            return Some(element);
+        }
+
+        element.children.iter().find_map(|child| match child {
+            XMLNode::Element(child) => find_element(child, name),
+            _ => None,
+        })
+    }
+
+    fn get_texts(element: &Element) -> Vec<String> {
+        element.children.iter().filter_map(|child| match child {
+            XMLNode::Text(text) => Some(text.clone()),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn collapses_whitespace_without_trimming() {
+        assert_eq!(normalize_whitespace("  foo \n\t bar  ", false), " foo bar ");
+        assert_eq!(normalize_whitespace("\n    ", false), " ");
+        assert_eq!(normalize_whitespace("  foo\n  bar", true), "  foo\n  bar");
+        assert_eq!(normalize_whitespace("\n", true), "\n");
+    }
+
+    #[test]
+    fn keeps_whitespace_between_spans_in_pre() {
+        let element = get_html_element("<pre><span>let a</span>\n<span>let b</span></pre>");
+        let pre = find_element(&element, "pre").unwrap();
+
+        assert_eq!(pre.children.len(), 3);
+        assert_eq!(get_texts(pre), vec!["\n"]);
+    }
+
+    #[test]
+    fn keeps_textarea_text_as_written() {
+        let element = get_html_element("<textarea>  first\n\n    second  </textarea>");
+        let textarea = find_element(&element, "textarea").unwrap();
+
+        assert_eq!(get_texts(textarea), vec!["  first\n\n    second  "]);
+    }
+
+    #[test]
+    fn keeps_spaces_between_inline_siblings() {
+        let element = get_html_element("<p>foo  <b>bar</b>\n baz</p><p><b>a</b> <i>b</i></p>");
+        let body = find_element(&element, "body").unwrap();
+
+        let paragraphs: Vec<&Element> = body.children.iter().filter_map(|child| match child {
+            XMLNode::Element(child) => Some(child),
+            _ => None,
+        }).collect();
+
+        assert_eq!(get_texts(paragraphs[0]), vec!["foo ", " baz"]);
+        assert_eq!(get_texts(paragraphs[1]), vec![" "]);
+    }
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    #[test]
+    fn documents_can_be_analysed_on_other_threads() {
+        let html = "<html><head><title>Hello</title></head><body><p>hi</p></body></html>";
+        let mut document = Document::from_string(html.to_string(), &None).unwrap();
+
+        assert_eq!(document.metadata.title.as_deref(), Some("Hello"));
+        assert_send(&document);
+        assert_send(&document.perform_analysis(Arc::new(VoidProvider)));
+        assert_send(&crate::organization::organize(Arc::new(VoidProvider), document, &None));
+    }
+}
@@ -0,0 +1,968 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use csv::{Terminator, WriterBuilder};
+use xmltree::{Element, EmitterConfig, XMLNode};
+
+use crate::prelude::*;
+use crate::data_node::DataNode;
+use crate::document::{Document, DocumentMetadata, DocumentType};
+use crate::document_format::{DocumentFormat, FormatStrategy};
+use crate::graph_node::Graph;
+use crate::sqlite_export::write_nodeset_to_sqlite;
+
+/// Renders a node set as a document in the requested format
+pub fn build_document(nodeset: &NodeSet, document_format: &DocumentFormat) -> Result<Document, Errors> {
+    log::trace!("In build_document");
+
+    let data = match document_format.format_type {
+        DocumentType::JSON => render_json(nodeset, document_format)?,
+        DocumentType::CSV => render_csv(nodeset, document_format)?,
+        DocumentType::XML => render_xml(nodeset, document_format)?,
+        DocumentType::YAML => render_yaml(nodeset, document_format)?,
+        DocumentType::SQLITE => {
+            let database_path = document_format.database_path.as_deref().ok_or_else(|| {
+                log::error!("SQLite output needs a database path");
+                Errors::UnexpectedOutputFormat
+            })?;
+
+            write_nodeset_to_sqlite(nodeset, database_path)?
+        },
+        _ => {
+            log::error!("Unsupported output format: {:?}", document_format.format_type);
+            return Err(Errors::UnexpectedOutputFormat);
+        }
+    };
+
+    Ok(Document {
+        document_type: document_format.format_type.clone(),
+        metadata: DocumentMetadata {
+            encoding: document_format.encoding.clone(),
+            ..DocumentMetadata::default()
+        },
+        data,
+    })
+}
+
+fn render_json(nodeset: &NodeSet, document_format: &DocumentFormat) -> Result<String, Errors> {
+    let output = get_output_value(nodeset, document_format)?;
+
+    to_json_string(&output, document_format.indent, &document_format.line_ending)
+}
+
+fn render_yaml(nodeset: &NodeSet, document_format: &DocumentFormat) -> Result<String, Errors> {
+    let output = get_output_value(nodeset, document_format)?;
+    let indent = document_format.indent.unwrap_or(2).max(2);
+
+    let mut yaml = get_yaml_lines(&output, indent)?.join("\n");
+    yaml.push('\n');
+
+    Ok(apply_line_ending(yaml, &document_format.line_ending))
+}
+
+/// Records laid out according to the format strategy, along with any
+/// structured data, ready to be serialized
+fn get_output_value(nodeset: &NodeSet, document_format: &DocumentFormat) -> Result<Value, Errors> {
+    let strategy = document_format.strategy.clone().unwrap_or(FormatStrategy::Simple);
+
+    let records: Vec<Value> = match strategy {
+        FormatStrategy::Simple => nodeset.data_nodes
+            .iter()
+            .map(|data_node| Value::Object(get_record(data_node)))
+            .collect(),
+        FormatStrategy::Flattened => nodeset.data_nodes
+            .iter()
+            .map(|data_node| Value::Object(get_flattened_record(data_node)))
+            .collect(),
+        FormatStrategy::Nested => {
+            let graph_root = get_graph_root(nodeset)?;
+            let data_nodes = get_data_nodes_by_id(nodeset);
+
+            get_nested_record(graph_root, &data_nodes).into_iter().collect()
+        }
+    };
+
+    let structured_data = serde_json::to_value(&nodeset.structured_data).map_err(|err| {
+        log::error!("Could not serialize structured data: {}", err);
+        Errors::UnexpectedError
+    })?;
+
+    let mut output = Value::Object(Map::from_iter([
+        ("records".to_string(), Value::Array(records)),
+        ("structured_data".to_string(), structured_data),
+    ]));
+
+    if document_format.exclude_nulls.unwrap_or(false) {
+        remove_nulls(&mut output);
+    }
+
+    Ok(output)
+}
+
+pub(crate) fn get_graph_root(nodeset: &NodeSet) -> Result<&Graph, Errors> {
+    nodeset.graph_root.as_ref().ok_or_else(|| {
+        log::error!("Output format needs the graph of the node set");
+        Errors::UnexpectedOutputFormat
+    })
+}
+
+pub(crate) fn get_data_nodes_by_id(nodeset: &NodeSet) -> HashMap<String, Arc<DataNode>> {
+    nodeset.data_nodes
+        .iter()
+        .map(|data_node| (data_node.id.to_string(), Arc::clone(data_node)))
+        .collect()
+}
+
+/// A single record as a line of newline delimited JSON, ending in the
+/// format's line ending. Records can't be nested one per line, so the
+/// nested strategy writes them as simple records.
+pub fn get_ndjson_line(data_node: &DataNode, document_format: &DocumentFormat) -> Result<String, Errors> {
+    let mut record = match document_format.strategy {
+        Some(FormatStrategy::Flattened) => Value::Object(get_flattened_record(data_node)),
+        _ => Value::Object(get_record(data_node)),
+    };
+
+    if document_format.exclude_nulls.unwrap_or(false) {
+        remove_nulls(&mut record);
+    }
+
+    let mut line = to_json_string(&record, None, &None)?;
+    line.push_str(document_format.line_ending.as_deref().unwrap_or("\n"));
+
+    Ok(line)
+}
+
+/// A data node as a JSON object. Fields stay nested under `fields`.
+pub fn get_record(data_node: &DataNode) -> Map<String, Value> {
+    let fields: Map<String, Value> = data_node.fields
+        .iter()
+        .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+        .collect();
+
+    Map::from_iter([
+        ("id".to_string(), Value::String(data_node.id.to_string())),
+        ("lineage".to_string(), Value::String(data_node.lineage.to_string())),
+        ("hash".to_string(), data_node.hash.to_string().map_or(Value::Null, Value::String)),
+        ("description".to_string(), Value::String(data_node.description.clone())),
+        ("fields".to_string(), Value::Object(fields)),
+        ("provenance".to_string(), serde_json::to_value(&data_node.provenance).unwrap_or(Value::Null)),
+    ])
+}
+
+fn get_flattened_record(data_node: &DataNode) -> Map<String, Value> {
+    let mut flattened: Map<String, Value> = Map::new();
+    flatten_value(None, Value::Object(get_record(data_node)), &mut flattened);
+    flattened
+}
+
+/// The record of a graph node with the records of its children under
+/// `children`, recursively
+fn get_nested_record(
+    graph_node: &Graph,
+    data_nodes: &HashMap<String, Arc<DataNode>>,
+) -> Option<Value> {
+    let graph_node = read_lock!(graph_node);
+    let data_node = data_nodes.get(&graph_node.data_node_id.to_string())?;
+
+    let children: Vec<Value> = graph_node.children
+        .iter()
+        .filter_map(|child| get_nested_record(child, data_nodes))
+        .collect();
+
+    let mut record = get_record(data_node);
+    record.insert("children".to_string(), Value::Array(children));
+
+    Some(Value::Object(record))
+}
+
+/// Folds nested objects and arrays into a single level, joining keys with
+/// dots, so `{"a": {"b": 1}}` becomes `{"a.b": 1}`
+pub fn flatten_value(prefix: Option<&str>, value: Value, flattened: &mut Map<String, Value>) {
+    let join = |key: &str| match prefix {
+        Some(prefix) => format!("{}.{}", prefix, key),
+        None => key.to_string(),
+    };
+
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            for (key, value) in object {
+                flatten_value(Some(&join(&key)), value, flattened);
+            }
+        },
+        Value::Array(values) if !values.is_empty() => {
+            for (index, value) in values.into_iter().enumerate() {
+                flatten_value(Some(&join(&index.to_string())), value, flattened);
+            }
+        },
+        value => {
+            flattened.insert(prefix.unwrap_or_default().to_string(), value);
+        }
+    }
+}
+
+fn remove_nulls(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.retain(|_, value| !value.is_null());
+            object.values_mut().for_each(remove_nulls);
+        },
+        Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+/// Serializes compactly, or pretty printed with the given number of spaces
+/// per level, replacing newlines with the requested line ending
+fn to_json_string(
+    value: &Value,
+    indent: Option<usize>,
+    line_ending: &Option<String>,
+) -> Result<String, Errors> {
+    let serialized = match indent {
+        Some(indent) => {
+            let indent = " ".repeat(indent);
+            let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+            let mut buffer: Vec<u8> = Vec::new();
+            let mut serializer = serde_json::Serializer::with_formatter(&mut buffer, formatter);
+
+            value.serialize(&mut serializer)
+                .map(|_| String::from_utf8(buffer).expect("JSON output is UTF-8"))
+        },
+        None => serde_json::to_string(value),
+    };
+
+    let serialized = serialized.map_err(|err| {
+        log::error!("Could not serialize output: {}", err);
+        Errors::UnexpectedError
+    })?;
+
+    Ok(apply_line_ending(serialized, line_ending))
+}
+
+/// Replaces `\n` with the requested line ending
+pub fn apply_line_ending(text: String, line_ending: &Option<String>) -> String {
+    match line_ending.as_deref() {
+        Some(line_ending) if line_ending != "\n" => text.replace('\n', line_ending),
+        _ => text,
+    }
+}
+
+/// Named cells of a CSV row, in column order
+type Row = Vec<(String, String)>;
+
+/// Nodes sharing a lineage, and so the same shape, at the same depth
+struct RecordType {
+    depth: usize,
+    records: Vec<Graph>,
+    /// Data node ids of the nodes above the records
+    ancestor_ids: HashSet<String>,
+}
+
+/// Renders each record type that fits a table as a section of rows, the
+/// best fitting first, with sections separated by a blank line. Record types
+/// inside or around one already written are left out, since their fields are
+/// already part of its rows. Everything beneath a record is flattened into
+/// its columns, named after the path to the field such as `td[2]/a[1].href`.
+fn render_csv(nodeset: &NodeSet, document_format: &DocumentFormat) -> Result<String, Errors> {
+    let graph_root = get_graph_root(nodeset)?;
+    let data_nodes = get_data_nodes_by_id(nodeset);
+
+    let mut record_types: Vec<RecordType> = Vec::new();
+    let mut record_type_indices: HashMap<String, usize> = HashMap::new();
+    collect_record_types(
+        graph_root,
+        &mut Vec::new(),
+        &data_nodes,
+        &mut record_types,
+        &mut record_type_indices,
+    );
+
+    let mut candidates: Vec<_> = record_types
+        .iter()
+        .map(|record_type| {
+            let rows: Vec<Row> = record_type.records
+                .iter()
+                .map(|record| {
+                    let mut columns: Row = Vec::new();
+                    collect_columns(record, None, &data_nodes, &mut columns);
+                    columns
+                })
+                .collect();
+
+            (get_record_type_score(&rows), Reverse(record_type.depth), record_type, rows)
+        })
+        .collect();
+
+    // Stable, so equally good record types keep document order
+    candidates.sort_by_key(|(score, depth, _, _)| Reverse((*score, *depth)));
+
+    let mut covered_ids: HashSet<String> = HashSet::new();
+    let mut sections: Vec<Vec<Row>> = Vec::new();
+
+    for ((is_tabular, _), _, record_type, rows) in candidates {
+        let record_ids: Vec<String> = record_type.records
+            .iter()
+            .map(|record| read_lock!(record).data_node_id.to_string())
+            .collect();
+
+        if record_ids.iter().any(|id| covered_ids.contains(id)) {
+            continue;
+        }
+
+        if !sections.is_empty() && !is_tabular {
+            log::info!(
+                "Leaving {} record(s) out of CSV output, as they don't repeat with shared columns",
+                rows.len()
+            );
+            continue;
+        }
+
+        log::debug!("Writing {} records as rows", rows.len());
+
+        for record in record_type.records.iter() {
+            collect_subtree_ids(record, &mut covered_ids);
+        }
+        covered_ids.extend(record_type.ancestor_ids.iter().cloned());
+
+        sections.push(rows);
+    }
+
+    let delimiter = document_format.custom_delimiter.unwrap_or(',');
+
+    if !delimiter.is_ascii() {
+        log::error!("CSV delimiter must be a single byte character: {:?}", delimiter);
+        return Err(Errors::UnexpectedOutputFormat);
+    }
+
+    let (terminator, line_ending) = match document_format.line_ending.as_deref() {
+        None | Some("\n") => (Terminator::Any(b'\n'), "\n"),
+        Some("\r\n") => (Terminator::CRLF, "\r\n"),
+        Some(line_ending) if line_ending.len() == 1 => {
+            (Terminator::Any(line_ending.as_bytes()[0]), line_ending)
+        },
+        Some(line_ending) => {
+            log::error!("Unsupported CSV line ending: {:?}", line_ending);
+            return Err(Errors::UnexpectedOutputFormat);
+        }
+    };
+
+    let rendered_sections = sections
+        .iter()
+        .map(|rows| render_csv_section(rows, delimiter as u8, terminator, document_format))
+        .collect::<Result<Vec<String>, Errors>>()?;
+
+    Ok(rendered_sections.join(line_ending))
+}
+
+fn render_csv_section(
+    rows: &[Row],
+    delimiter: u8,
+    terminator: Terminator,
+    document_format: &DocumentFormat,
+) -> Result<String, Errors> {
+    let mut headers: Vec<String> = Vec::new();
+
+    for (name, _) in rows.iter().flatten() {
+        if !headers.contains(name) {
+            headers.push(name.clone());
+        }
+    }
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(delimiter)
+        .terminator(terminator)
+        .from_writer(Vec::new());
+
+    let write_error = |err: csv::Error| {
+        log::error!("Could not write CSV record: {}", err);
+        Errors::UnexpectedError
+    };
+
+    if document_format.headers.unwrap_or(true) {
+        writer.write_record(&headers).map_err(write_error)?;
+    }
+
+    for row in rows.iter() {
+        let record = headers.iter().map(|header| {
+            row.iter()
+                .find(|(name, _)| name == header)
+                .map_or("", |(_, value)| value.as_str())
+        });
+
+        writer.write_record(record).map_err(write_error)?;
+    }
+
+    let data = writer.into_inner().map_err(|err| {
+        log::error!("Could not flush CSV output: {}", err);
+        Errors::UnexpectedError
+    })?;
+
+    Ok(String::from_utf8(data).expect("CSV output is UTF-8"))
+}
+
+/// How well a record type lends itself to rows: first whether it has
+/// several records sharing a column, then the number of records times the
+/// number of columns they all share. Repeated records are preferred over a
+/// single one, however many columns it has.
+fn get_record_type_score(rows: &[Row]) -> (bool, usize) {
+    let shared_column_count = match rows.split_first() {
+        Some((first, rest)) => first
+            .iter()
+            .filter(|(name, _)| {
+                rest.iter().all(|row| row.iter().any(|(other, _)| other == name))
+            })
+            .count(),
+        None => 0,
+    };
+
+    (rows.len() >= 2 && shared_column_count > 0, rows.len() * shared_column_count)
+}
+
+/// Groups nodes into record types by lineage
+fn collect_record_types(
+    graph_node: &Graph,
+    ancestor_ids: &mut Vec<String>,
+    data_nodes: &HashMap<String, Arc<DataNode>>,
+    record_types: &mut Vec<RecordType>,
+    record_type_indices: &mut HashMap<String, usize>,
+) {
+    let graph_node_lock = read_lock!(graph_node);
+    let data_node_id = graph_node_lock.data_node_id.to_string();
+
+    if let Some(data_node) = data_nodes.get(&data_node_id) {
+        let lineage = data_node.lineage.to_string();
+        let index = *record_type_indices.entry(lineage).or_insert_with(|| {
+            record_types.push(RecordType {
+                depth: ancestor_ids.len(),
+                records: Vec::new(),
+                ancestor_ids: HashSet::new(),
+            });
+            record_types.len() - 1
+        });
+
+        record_types[index].records.push(Arc::clone(graph_node));
+        record_types[index].ancestor_ids.extend(ancestor_ids.iter().cloned());
+    }
+
+    ancestor_ids.push(data_node_id);
+
+    for child in graph_node_lock.children.iter() {
+        collect_record_types(child, ancestor_ids, data_nodes, record_types, record_type_indices);
+    }
+
+    ancestor_ids.pop();
+}
+
+fn collect_subtree_ids(graph_node: &Graph, ids: &mut HashSet<String>) {
+    let graph_node = read_lock!(graph_node);
+    ids.insert(graph_node.data_node_id.to_string());
+
+    for child in graph_node.children.iter() {
+        collect_subtree_ids(child, ids);
+    }
+}
+
+/// Fields naming the node rather than holding its data
+pub(crate) fn is_structural_field(key: &str) -> bool {
+    key == "tag" || key == "namespace"
+}
+
+/// Flattens a node and its descendants into named columns. A column is named
+/// after the elements leading to the field, numbered among siblings of the
+/// same tag, as in `td[2]/a[1].href`. Text and comments belong to their
+/// parent, and a name repeated within it is suffixed, as in `text_2`.
+fn collect_columns(
+    graph_node: &Graph,
+    prefix: Option<&str>,
+    data_nodes: &HashMap<String, Arc<DataNode>>,
+    columns: &mut Row,
+) {
+    let graph_node = read_lock!(graph_node);
+    let data_node = match data_nodes.get(&graph_node.data_node_id.to_string()) {
+        Some(data_node) => data_node,
+        None => return,
+    };
+
+    let mut keys: Vec<&String> = data_node.fields
+        .keys()
+        .filter(|key| !is_structural_field(key))
+        .collect();
+    keys.sort();
+
+    for key in keys {
+        let base = match prefix {
+            Some(prefix) => format!("{}.{}", prefix, key),
+            None => key.to_string(),
+        };
+        let mut name = base.clone();
+        let mut suffix = 2;
+
+        while columns.iter().any(|(existing, _)| *existing == name) {
+            name = format!("{}_{}", base, suffix);
+            suffix += 1;
+        }
+
+        columns.push((name, data_node.fields[key].clone()));
+    }
+
+    let mut tag_counts: HashMap<String, usize> = HashMap::new();
+
+    for child in graph_node.children.iter() {
+        let child_tag = data_nodes
+            .get(&read_lock!(child).data_node_id.to_string())
+            .and_then(|child| child.fields.get("tag"));
+
+        let child_prefix = child_tag.map(|tag| {
+            let count = tag_counts.entry(tag.clone()).or_insert(0);
+            *count += 1;
+
+            match prefix {
+                Some(prefix) => format!("{}/{}[{}]", prefix, tag, count),
+                None => format!("{}[{}]", tag, count),
+            }
+        });
+
+        collect_columns(child, child_prefix.as_deref().or(prefix), data_nodes, columns);
+    }
+}
+
+/// Lays a value out as block style YAML with the given number of spaces per
+/// level. serde_yaml always indents by two, so only scalars are left to it.
+fn get_yaml_lines(value: &Value, indent: usize) -> Result<Vec<String>, Errors> {
+    let padding = " ".repeat(indent);
+    let mut lines: Vec<String> = Vec::new();
+
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            for (key, child) in object.iter() {
+                let key = get_yaml_scalar(&Value::String(key.clone()))?;
+
+                if is_yaml_block(child) {
+                    lines.push(format!("{}:", key));
+                    lines.extend(
+                        get_yaml_lines(child, indent)?
+                            .into_iter()
+                            .map(|line| format!("{}{}", padding, line))
+                    );
+                } else {
+                    lines.push(format!("{}: {}", key, get_yaml_scalar(child)?));
+                }
+            }
+        },
+        Value::Array(values) if !values.is_empty() => {
+            // The dash counts towards the indentation of the item
+            let dash = format!("-{}", &padding[1..]);
+
+            for child in values.iter() {
+                let child_lines = if is_yaml_block(child) {
+                    get_yaml_lines(child, indent)?
+                } else {
+                    vec![get_yaml_scalar(child)?]
+                };
+
+                for (index, line) in child_lines.into_iter().enumerate() {
+                    let prefix = if index == 0 { &dash } else { &padding };
+                    lines.push(format!("{}{}", prefix, line));
+                }
+            }
+        },
+        _ => lines.push(get_yaml_scalar(value)?),
+    }
+
+    Ok(lines)
+}
+
+fn is_yaml_block(value: &Value) -> bool {
+    match value {
+        Value::Object(object) => !object.is_empty(),
+        Value::Array(values) => !values.is_empty(),
+        _ => false,
+    }
+}
+
+/// A scalar, or empty collection, on a single line. Multiline strings are
+/// written double quoted, which YAML reads the same way as JSON.
+fn get_yaml_scalar(value: &Value) -> Result<String, Errors> {
+    let scalar = serde_yaml::to_string(value).map_err(|err| {
+        log::error!("Could not serialize YAML: {}", err);
+        Errors::UnexpectedError
+    })?;
+    let scalar = scalar.trim_end_matches('\n');
+
+    if scalar.contains('\n') {
+        return serde_json::to_string(value).map_err(|err| {
+            log::error!("Could not serialize YAML: {}", err);
+            Errors::UnexpectedError
+        });
+    }
+
+    Ok(scalar.to_string())
+}
+
+/// Name of the element wrapping the records of an XML document
+const XML_ROOT_ELEMENT: &str = "document";
+
+const XML_RECORDS_ELEMENT: &str = "records";
+
+const XML_STRUCTURED_DATA_ELEMENT: &str = "structured_data";
+
+/// Name of the element wrapping each structured data item, and each value
+/// of an array without a key of its own
+const XML_ITEM_ELEMENT: &str = "item";
+
+/// Renders the graph as XML, next to any structured data as JSON and YAML
+/// output have it. Nodes with a tag become elements of that name, and every
+/// other field becomes a child element named after the field.
+fn render_xml(nodeset: &NodeSet, document_format: &DocumentFormat) -> Result<String, Errors> {
+    let graph_root = get_graph_root(nodeset)?;
+    let data_nodes = get_data_nodes_by_id(nodeset);
+
+    let mut records = Element::new(XML_RECORDS_ELEMENT);
+    records.children = get_xml_nodes(graph_root, &data_nodes);
+
+    let structured_data = serde_json::to_value(&nodeset.structured_data).map_err(|err| {
+        log::error!("Could not serialize structured data: {}", err);
+        Errors::UnexpectedError
+    })?;
+
+    let mut root = Element::new(XML_ROOT_ELEMENT);
+    root.children.push(XMLNode::Element(records));
+    root.children.extend(get_xml_value_nodes(XML_STRUCTURED_DATA_ELEMENT, &structured_data, true));
+
+    let mut config = EmitterConfig::new()
+        .perform_indent(document_format.indent.is_some())
+        .indent_string(" ".repeat(document_format.indent.unwrap_or(0)));
+
+    if let Some(line_ending) = &document_format.line_ending {
+        config = config.line_separator(line_ending.clone());
+    }
+
+    let mut data: Vec<u8> = Vec::new();
+
+    root.write_with_config(&mut data, config).map_err(|err| {
+        log::error!("Could not write XML: {}", err);
+        Errors::UnexpectedError
+    })?;
+
+    Ok(String::from_utf8(data).expect("XML output is UTF-8"))
+}
+
+fn get_xml_nodes(
+    graph_node: &Graph,
+    data_nodes: &HashMap<String, Arc<DataNode>>,
+) -> Vec<XMLNode> {
+    let graph_node = read_lock!(graph_node);
+    let data_node = match data_nodes.get(&graph_node.data_node_id.to_string()) {
+        Some(data_node) => data_node,
+        None => return Vec::new(),
+    };
+
+    let mut keys: Vec<&String> = data_node.fields
+        .keys()
+        .filter(|key| !is_structural_field(key))
+        .collect();
+    keys.sort();
+
+    let mut nodes: Vec<XMLNode> = keys
+        .into_iter()
+        .map(|key| {
+            let mut field = Element::new(&get_xml_name(key));
+            field.children.push(XMLNode::Text(data_node.fields[key].clone()));
+            XMLNode::Element(field)
+        })
+        .collect();
+
+    for child in graph_node.children.iter() {
+        nodes.extend(get_xml_nodes(child, data_nodes));
+    }
+
+    // Text and comments have no element of their own, so their fields
+    // belong to the parent
+    match data_node.fields.get("tag") {
+        Some(tag) => {
+            let mut element = Element::new(&get_xml_name(tag));
+            element.children = nodes;
+            vec![XMLNode::Element(element)]
+        },
+        None => nodes,
+    }
+}
+
+/// Lays a JSON value out as elements named after their keys. Arrays become
+/// elements repeating the name of their key, or items inside an element of
+/// that name when `wrap_arrays` is set.
+fn get_xml_value_nodes(name: &str, value: &Value, wrap_arrays: bool) -> Vec<XMLNode> {
+    let mut element = Element::new(&get_xml_name(name));
+
+    match value {
+        Value::Array(values) if wrap_arrays => {
+            for value in values.iter() {
+                element.children.extend(get_xml_value_nodes(XML_ITEM_ELEMENT, value, false));
+            }
+        },
+        Value::Array(values) => {
+            return values
+                .iter()
+                .flat_map(|value| get_xml_value_nodes(name, value, false))
+                .collect();
+        },
+        Value::Object(object) => {
+            for (key, value) in object.iter() {
+                element.children.extend(get_xml_value_nodes(key, value, false));
+            }
+        },
+        Value::Null => {},
+        Value::String(text) => element.children.push(XMLNode::Text(text.clone())),
+        other => element.children.push(XMLNode::Text(other.to_string())),
+    }
+
+    vec![XMLNode::Element(element)]
+}
+
+/// Turns a tag or field name into a valid XML name without a prefix, since
+/// no namespaces are declared in the output
+fn get_xml_name(name: &str) -> String {
+    let mut xml_name: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+
+    if !xml_name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        xml_name.insert(0, '_');
+    }
+
+    xml_name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use crate::provider::VoidProvider;
+    use crate::traverse::{TraversalWithContext, traverse_with_context};
+
+    async fn get_nodeset(text: &str) -> NodeSet {
+        let mut document = Document::from_string(text.to_string(), &None).unwrap();
+        let profile = document.perform_analysis(Arc::new(VoidProvider)).await.unwrap();
+        let TraversalWithContext { nodeset, .. } = traverse_with_context(&profile, document).unwrap();
+
+        nodeset
+    }
+
+    fn row(cells: &[(&str, &str)]) -> Row {
+        cells.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn writes_each_record_type_as_a_section() {
+        let nodeset = get_nodeset(
+            "<html><body>\
+            <table><tr><td class=\"a\">1</td><td>2</td></tr><tr><td class=\"b\">3</td><td>4</td></tr></table>\
+            <ul><li>x</li><li>y</li><li>z</li></ul>\
+            </body></html>"
+        ).await;
+
+        let csv = render_csv(&nodeset, &DocumentFormat::default()).unwrap();
+
+        assert_eq!(csv, "td[1].class,td[1].text,td[2].text\na,1,2\nb,3,4\n\ntext\nx\ny\nz\n");
+    }
+
+    #[tokio::test]
+    async fn names_columns_after_field_paths() {
+        let nodeset = get_nodeset(
+            "<html><body><table>\
+            <tr><td>one</td><td><a href=\"/1\">first</a> <a href=\"/1/edit\">edit</a></td></tr>\
+            <tr><td>two</td><td><a href=\"/2\">second</a> <a href=\"/2/edit\">edit</a></td></tr>\
+            </table></body></html>"
+        ).await;
+
+        let csv = render_csv(&nodeset, &DocumentFormat::default()).unwrap();
+        let header = csv.lines().next().unwrap();
+
+        assert_eq!(
+            header,
+            "td[1].text,td[2]/a[1].href,td[2]/a[1].text,td[2].text,td[2]/a[2].href,td[2]/a[2].text"
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_a_lone_record_when_nothing_repeats() {
+        let nodeset = get_nodeset("<html><body><p>hello</p></body></html>").await;
+
+        let csv = render_csv(&nodeset, &DocumentFormat::default()).unwrap();
+
+        assert_eq!(csv, "body[1]/p[1].text\nhello\n");
+    }
+
+    #[test]
+    fn repeated_records_outscore_a_wide_single_record() {
+        let wide = vec![row(&[("a", "1"), ("b", "2"), ("c", "3"), ("d", "4"), ("e", "5")])];
+        let repeated = vec![row(&[("a", "1")]), row(&[("a", "2")])];
+        let unshared = vec![row(&[("a", "1")]), row(&[("b", "2")])];
+
+        assert!(get_record_type_score(&repeated) > get_record_type_score(&wide));
+        assert!(get_record_type_score(&unshared) < get_record_type_score(&repeated));
+        assert!(!get_record_type_score(&unshared).0);
+    }
+
+    #[test]
+    fn lays_yaml_out_with_the_given_indent() {
+        let value = serde_json::json!({"a": {"b": [1, {"c": "d", "e": []}]}, "f": {}});
+
+        assert_eq!(
+            get_yaml_lines(&value, 2).unwrap().join("\n"),
+            "a:\n  b:\n    - 1\n    - c: d\n      e: []\nf: {}"
+        );
+        assert_eq!(
+            get_yaml_lines(&value, 4).unwrap().join("\n"),
+            "a:\n    b:\n        -   1\n        -   c: d\n            e: []\nf: {}"
+        );
+    }
+
+    #[test]
+    fn yaml_reads_back_as_the_same_value() {
+        let value = serde_json::json!({
+            "multiline": "first\nsecond\n",
+            "looks like other types": ["yes", "null", "1.5", "", "- dash", "key: value"],
+            "nested": [[1, 2], [], [{"a": null, "b": true}]],
+            "quote\"d key": "#not a comment",
+        });
+
+        for indent in [2, 3, 4] {
+            let yaml = get_yaml_lines(&value, indent).unwrap().join("\n");
+            let parsed: Value = serde_yaml::from_str(&yaml).unwrap();
+
+            assert_eq!(parsed, value, "indent {}:\n{}", indent, yaml);
+        }
+    }
+
+    #[tokio::test]
+    async fn renders_records_as_yaml() {
+        let nodeset = get_nodeset("<html><body><p>hello</p></body></html>").await;
+        let document_format = DocumentFormat {
+            format_type: DocumentType::YAML,
+            line_ending: Some("\r\n".to_string()),
+            ..DocumentFormat::default()
+        };
+
+        let yaml = render_yaml(&nodeset, &document_format).unwrap();
+        let parsed: Value = serde_yaml::from_str(&yaml).unwrap();
+
+        assert!(yaml.ends_with("\r\n"));
+        assert!(!yaml.replace("\r\n", "").contains('\n'));
+        assert_eq!(parsed["records"].as_array().unwrap().len(), nodeset.data_nodes.len());
+    }
+
+    fn has_nulls(value: &Value) -> bool {
+        match value {
+            Value::Null => true,
+            Value::Object(object) => object.values().any(has_nulls),
+            Value::Array(values) => values.iter().any(has_nulls),
+            _ => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn renders_simple_records_as_compact_json() {
+        let nodeset = get_nodeset("<html><body><p>hello</p></body></html>").await;
+
+        let json = render_json(&nodeset, &DocumentFormat::default()).unwrap();
+        let output: Value = serde_json::from_str(&json).unwrap();
+        let records = output["records"].as_array().unwrap();
+
+        assert!(!json.contains('\n'));
+        assert_eq!(records.len(), nodeset.data_nodes.len());
+        assert_eq!(records[0]["fields"]["tag"], "html");
+        assert_eq!(records[0]["id"], nodeset.data_nodes[0].id.to_string());
+        assert!(output["structured_data"].is_array());
+    }
+
+    #[tokio::test]
+    async fn renders_flattened_records_with_dotted_keys() {
+        let nodeset = get_nodeset("<html><body><p>hello</p></body></html>").await;
+        let document_format = DocumentFormat {
+            strategy: Some(FormatStrategy::Flattened),
+            indent: Some(4),
+            ..DocumentFormat::default()
+        };
+
+        let json = render_json(&nodeset, &document_format).unwrap();
+        let output: Value = serde_json::from_str(&json).unwrap();
+        let records = output["records"].as_array().unwrap();
+
+        assert!(json.contains("\n    \"records\""));
+        assert_eq!(records.len(), nodeset.data_nodes.len());
+        assert_eq!(records[0]["fields.tag"], "html");
+        assert!(records.iter().all(|record| record.get("fields").is_none()));
+        assert!(records.iter().any(|record| record["fields.text"] == "hello"));
+    }
+
+    #[tokio::test]
+    async fn renders_nested_records_under_their_parents() {
+        let nodeset = get_nodeset("<html><body><p>hello</p></body></html>").await;
+        let document_format = DocumentFormat {
+            strategy: Some(FormatStrategy::Nested),
+            exclude_nulls: Some(true),
+            ..DocumentFormat::default()
+        };
+
+        let json = render_json(&nodeset, &document_format).unwrap();
+        let output: Value = serde_json::from_str(&json).unwrap();
+        let records = output["records"].as_array().unwrap();
+
+        fn find<'a>(record: &'a Value, tag: &str) -> Option<&'a Value> {
+            if record["fields"]["tag"] == tag {
+                return Some(record);
+            }
+
+            record["children"].as_array()?.iter().find_map(|child| find(child, tag))
+        }
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["fields"]["tag"], "html");
+
+        let paragraph = find(&records[0], "p").unwrap();
+        assert!(find(&records[0], "body").unwrap()["children"].as_array().unwrap().contains(paragraph));
+        assert_eq!(paragraph["children"][0]["fields"]["text"], "hello");
+        assert!(!has_nulls(&output));
+
+        let document_format = DocumentFormat { exclude_nulls: None, ..document_format };
+        let json = render_json(&nodeset, &document_format).unwrap();
+        assert!(has_nulls(&serde_json::from_str(&json).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn renders_structured_data_next_to_xml_records() {
+        let nodeset = get_nodeset(
+            "<html><head><script type=\"application/ld+json\">\
+            {\"@type\": \"Product\", \"name\": \"Widget\", \"offers\": [{\"price\": 5}, {\"price\": 7}]}\
+            </script></head><body><p>hello</p></body></html>"
+        ).await;
+
+        let xml = render_xml(&nodeset, &DocumentFormat::default()).unwrap();
+        let root = Element::parse(xml.as_bytes()).unwrap();
+
+        let get_text = |element: &Element| element.get_text().map(|text| text.to_string());
+
+        assert_eq!(root.name, XML_ROOT_ELEMENT);
+        assert!(root.get_child(XML_RECORDS_ELEMENT).unwrap().get_child("html").is_some());
+
+        let item = root
+            .get_child(XML_STRUCTURED_DATA_ELEMENT).unwrap()
+            .get_child(XML_ITEM_ELEMENT).unwrap();
+        let value = item.get_child("value").unwrap();
+        let prices: Vec<Option<String>> = value.children
+            .iter()
+            .filter_map(|node| node.as_element())
+            .filter(|element| element.name == "offers")
+            .map(|offer| get_text(offer.get_child("price").unwrap()))
+            .collect();
+
+        assert_eq!(get_text(item.get_child("types").unwrap()).as_deref(), Some("Product"));
+        assert_eq!(get_text(value.get_child("name").unwrap()).as_deref(), Some("Widget"));
+        assert_eq!(get_text(value.get_child("_type").unwrap()).as_deref(), Some("Product"));
+        assert_eq!(prices, vec![Some("5".to_string()), Some("7".to_string())]);
+    }
+}
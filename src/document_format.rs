@@ -1,23 +1,32 @@
 use crate::document::DocumentType;
 
-#[derive(Debug, Clone)]
-enum FormatStrategy {
+/// How records are laid out in the output document
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatStrategy {
+    /// A list of records, each with its fields as a nested object
     Simple,
+    /// A list of records whose nested objects are folded into dotted keys
     Flattened,
+    /// Records nested under their parents, following the document graph
     Nested,
 }
 
 #[derive(Debug, Clone)]
 pub struct DocumentFormat {
-    format_type: DocumentType,
-    encoding: Option<String>,
-    strategy: Option<FormatStrategy>,
-    indent: Option<usize>,
-    line_ending: Option<String>,
-    headers: Option<bool>,
-    wrap_text: Option<bool>,
-    exclude_nulls: Option<bool>,
-    custom_delimiter: Option<char>,
+    pub format_type: DocumentType,
+    pub encoding: Option<String>,
+    pub strategy: Option<FormatStrategy>,
+    /// Spaces per level of indentation. Output is compact when unset.
+    pub indent: Option<usize>,
+    /// Line ending used in place of `\n`, such as `\r\n`
+    pub line_ending: Option<String>,
+    pub headers: Option<bool>,
+    pub wrap_text: Option<bool>,
+    /// Leaves out keys whose value is null
+    pub exclude_nulls: Option<bool>,
+    pub custom_delimiter: Option<char>,
+    /// Database file written to by SQLite output
+    pub database_path: Option<String>,
 }
 
 impl Default for DocumentFormat {
@@ -32,6 +41,7 @@ impl Default for DocumentFormat {
             wrap_text: None,
             exclude_nulls: None,
             custom_delimiter: None,
+            database_path: None,
         }
     }
 }
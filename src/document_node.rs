@@ -1,24 +1,38 @@
 use serde::{Serialize, Deserialize};
 use xmltree::{XMLNode, Element};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::prelude::*;
 use crate::transformation::XMLElementTransformation;
+use crate::xml_document::{get_qualified_name, set_qualified_name};
+use crate::provenance::{Provenance, ProvenanceTree};
+use crate::document_builder::is_structural_field;
 
 #[derive(Clone, Debug)]
 pub struct DocumentNode {
     pub id: ID,
     data: XMLNode,
+    provenance: Arc<ProvenanceTree>,
 }
 
 impl DocumentNode {
     pub fn new(xml_node: XMLNode) -> Self {
+        DocumentNode::with_provenance(xml_node, Arc::new(ProvenanceTree::default()))
+    }
+
+    pub fn with_provenance(xml_node: XMLNode, provenance: Arc<ProvenanceTree>) -> Self {
         DocumentNode {
             id: ID::new(),
             data: xml_node.clone(),
+            provenance,
         }
     }
 
+    pub fn get_provenance(&self) -> Provenance {
+        self.provenance.provenance.clone()
+    }
+
     pub fn to_string_components(&self) -> (String, Option<String>) {
         match &self.data {
             XMLNode::Element(element_node) => {
@@ -30,7 +44,15 @@ impl DocumentNode {
             XMLNode::Text(text_node) => {
                 (text_node.to_string(), None)
             },
-            _ => panic!("Unexpected XML node type")
+            XMLNode::CData(text) => {
+                (format!("<![CDATA[{}]]>", text), None)
+            },
+            XMLNode::ProcessingInstruction(name, data) => {
+                (format!("<?{} {}?>", name, data.clone().unwrap_or_default()), None)
+            },
+            XMLNode::Comment(text) => {
+                (format!("<!--{}-->", text), None)
+            },
         }
     }
 
@@ -43,10 +65,11 @@ impl DocumentNode {
     pub fn from_transformations(
         xml_node: XMLNode,
         xml_element_transformation: XMLElementTransformation,
+        provenance: Arc<ProvenanceTree>,
     ) -> Option<Self> {
         match &xml_node {
             XMLNode::Element(element_node) => {
-                let mut element: Option<String> = Some(element_node.name.clone());
+                let mut element: Option<String> = Some(get_qualified_name(element_node));
                 let mut attributes: HashMap<String, String>  = HashMap::new();
 
                 for (attr, val) in element_node.attributes.iter() {
@@ -75,17 +98,14 @@ impl DocumentNode {
                     let mut transformed_node = xml_node.clone();
 
                     if let XMLNode::Element(ref mut elem) = transformed_node {
-                        elem.name = some_element;
+                        set_qualified_name(elem, &some_element);
                         elem.attributes = attributes;
                     }
 
-                    DocumentNode::new(transformed_node)
+                    DocumentNode::with_provenance(transformed_node, provenance)
                 })
             },
-            XMLNode::Text(_text_node) => {
-                Some(DocumentNode::new(xml_node))
-            },
-            _ => panic!("Unexpected XML node type")
+            _ => Some(DocumentNode::with_provenance(xml_node, provenance)),
         }
     }
 
@@ -93,59 +113,86 @@ impl DocumentNode {
         match &self.data {
             XMLNode::Element(element_node) => {
                 let mut fields = element_node.attributes.clone();
-                fields.insert("tag".to_string(), element_node.name.clone());
+                fields.insert("tag".to_string(), get_qualified_name(element_node));
+                if let Some(namespace) = &element_node.namespace {
+                    fields.insert("namespace".to_string(), namespace.clone());
+                }
                 fields
             }
             XMLNode::Text(text_node) => HashMap::from([
                 ("text".to_string(), text_node.to_string())
             ]),
-            _ => panic!("Unexpected XML node type")
+            XMLNode::CData(text) => HashMap::from([
+                ("text".to_string(), text.to_string()),
+                ("cdata".to_string(), "true".to_string()),
+            ]),
+            XMLNode::ProcessingInstruction(name, data) => HashMap::from([
+                ("tag".to_string(), format!("?{}", name)),
+                ("data".to_string(), data.clone().unwrap_or_default()),
+            ]),
+            XMLNode::Comment(text) => {
+                let mut fields = HashMap::from([
+                    ("comment".to_string(), text.trim().to_string())
+                ]);
+
+                if let Some(condition) = get_comment_condition(text) {
+                    fields.insert("condition".to_string(), condition);
+                }
+
+                fields
+            },
         }
     }
 
     pub fn get_description(&self) -> String {
         match &self.data {
             XMLNode::Element(element_node) => {
-                element_node.name.clone()
+                get_qualified_name(element_node)
             },
-            XMLNode::Text(text_node) => {
-                let mut description = text_node.to_string();
-                description.truncate(20);
-
-                description
+            XMLNode::Text(text_node) | XMLNode::CData(text_node) => {
+                text_node.chars().take(20).collect()
+            },
+            XMLNode::Comment(text) => {
+                format!("<!--{}", text.trim().chars().take(20).collect::<String>())
+            },
+            XMLNode::ProcessingInstruction(name, _) => {
+                format!("?{}", name)
             },
-            _ => panic!("Unexpected XML node type")
         }
     }
 
     pub fn get_children(
         &self,
-        xml_element_transformation: Option<XMLElementTransformation>
+        xml_element_transformation: Option<XMLElementTransformation>,
+        retain_comments: bool,
     ) -> Vec<DocumentNode> {
         match &self.data {
             XMLNode::Element(element_node) => {
                 element_node.children
                     .iter()
-                    .filter_map(|child| {
+                    .enumerate()
+                    .filter(|(_, child)| retain_comments || !matches!(child, XMLNode::Comment(_)))
+                    .filter_map(|(index, child)| {
+                        let provenance = self.provenance.get_child(index);
 
                         if let Some(xml_element_transformation) = &xml_element_transformation {
                             DocumentNode::from_transformations(
                                 child.clone(),
-                                xml_element_transformation.clone()
+                                xml_element_transformation.clone(),
+                                provenance
                             )
                         } else {
-                            Some(DocumentNode::new(child.clone()))
+                            Some(DocumentNode::with_provenance(child.clone(), provenance))
                         }
                     })
                     .collect()
             },
-            XMLNode::Text(text_node) => Vec::new(),
-            _ => panic!("Unexpected XML node type")
+            _ => Vec::new(),
         }
     }
 
     fn get_opening_tag(element: &Element) -> String {
-        let mut tag = format!("<{}", element.name);
+        let mut tag = format!("<{}", get_qualified_name(element));
 
         let mut attributes: Vec<(&String, &String)> = element.attributes.iter().collect();
 
@@ -161,6 +208,25 @@ impl DocumentNode {
     }
 
     fn get_closing_tag(element: &Element) -> String {
-        format!("</{}>", element.name)
+        format!("</{}>", get_qualified_name(element))
     }
 }
+
+/// The condition of a conditional comment such as `<!--[if lt IE 9]>...<![endif]-->`
+/// Renames a data key that would be mistaken for a field naming the node,
+/// such as `tag`, by prefixing an underscore. Keys that already start with
+/// one get another, so distinct keys stay distinct.
+pub fn escape_field_name(key: &str) -> String {
+    if is_structural_field(key.trim_start_matches('_')) {
+        format!("_{}", key)
+    } else {
+        key.to_string()
+    }
+}
+
+fn get_comment_condition(text: &str) -> Option<String> {
+    let condition = text.trim_start().strip_prefix('[')?;
+    let end = condition.find(']')?;
+
+    Some(condition[..end].trim().to_string())
+}
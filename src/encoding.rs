@@ -0,0 +1,76 @@
+use lazy_static::lazy_static;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+use regex::Regex;
+
+/// Number of leading bytes searched for an XML declaration or meta charset
+const PRESCAN_LENGTH: usize = 1024;
+
+lazy_static! {
+    static ref XML_DECLARATION_ENCODING: Regex = Regex::new(
+        r#"(?i)^<\?xml[^>]*\sencoding\s*=\s*["']([a-z0-9_.:\-]+)["']"#
+    ).unwrap();
+
+    static ref META_CHARSET: Regex = Regex::new(
+        r#"(?i)<meta[^>]*charset\s*=\s*["']?\s*([a-z0-9_.:\-]+)"#
+    ).unwrap();
+}
+
+/// Works out the encoding of raw input. In order of precedence: a byte
+/// order mark, the encoding named in an XML declaration, a `<meta charset>`
+/// or `http-equiv` content type, valid UTF-8, and finally a statistical
+/// guess.
+pub fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        log::debug!("Encoding from byte order mark: {}", encoding.name());
+        return encoding;
+    }
+
+    let head_length = bytes.len().min(PRESCAN_LENGTH);
+    let head = String::from_utf8_lossy(&bytes[..head_length]);
+    let head = head.trim_start();
+
+    let declared = XML_DECLARATION_ENCODING.captures(head)
+        .or_else(|| META_CHARSET.captures(head))
+        .and_then(|captures| Encoding::for_label(captures[1].as_bytes()));
+
+    if let Some(encoding) = declared {
+        log::debug!("Encoding declared in document: {}", encoding.name());
+
+        // A document that could declare UTF-16 in ASCII isn't UTF-16
+        return encoding.output_encoding();
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+
+    let encoding = detector.guess(None, true);
+    log::debug!("Guessed encoding: {}", encoding.name());
+
+    encoding
+}
+
+/// Transcodes raw input to UTF-8, returning the text along with the
+/// encoding it was decoded from. An explicit label overrides detection.
+pub fn decode_bytes(bytes: &[u8], label: Option<&str>) -> (String, &'static Encoding) {
+    let encoding = match label.map(|label| (label, Encoding::for_label(label.as_bytes()))) {
+        Some((_, Some(encoding))) => encoding,
+        Some((label, None)) => {
+            log::warn!("Unknown encoding {}, detecting it instead", label);
+            detect_encoding(bytes)
+        },
+        None => detect_encoding(bytes),
+    };
+
+    let (text, actual_encoding, had_errors) = encoding.decode(bytes);
+
+    if had_errors {
+        log::warn!("Input contained sequences that are invalid in {}", actual_encoding.name());
+    }
+
+    (text.into_owned(), actual_encoding)
+}
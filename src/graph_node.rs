@@ -9,6 +9,8 @@ pub type Graph = Arc<RwLock<GraphNode>>;
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct GraphNode {
     pub id: ID,
+    /// The data node this graph node was built from
+    pub data_node_id: ID,
     pub parents: Vec<Graph>,
     pub description: String,
     pub hash: Hash,
@@ -20,6 +22,7 @@ impl GraphNode {
     pub fn from_data_node(data_node: Arc<DataNode>, parents: Vec<Graph>) -> Self {
         GraphNode {
             id: ID::new(),
+            data_node_id: data_node.id.clone(),
             parents,
             description: data_node.description.clone(),
             hash: data_node.hash.clone(),
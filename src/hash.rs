@@ -24,6 +24,13 @@ impl Hash {
         }
     }
 
+    pub fn from_value(value: &str) -> Self {
+        Hash {
+            items: None,
+            value: Some(value.to_string()),
+        }
+    }
+
     pub fn from_items<U: ToString>(items: Vec<U>) -> Self {
         let string_items = items.into_iter().map(|item| item.to_string()).collect();
         Hash {
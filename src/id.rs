@@ -1,11 +1,11 @@
 use uuid::Uuid;
-use serde::{Serialize};
+use serde::{Serialize, Serializer};
 use serde::de::{self, Deserialize, Deserializer, Visitor, Error as SerdeError};
 use std::fmt;
 use std::str::FromStr;
 
 
-#[derive(Clone, Debug, Serialize, Hash)]
+#[derive(Clone, Debug, Hash)]
 pub struct ID {
     value: String
 }
@@ -36,6 +36,15 @@ impl PartialEq for ID {
 
 impl Eq for ID {}
 
+impl Serialize for ID {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.value)
+    }
+}
+
 impl<'de> Deserialize<'de> for ID {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -0,0 +1,106 @@
+use serde_json::Value;
+use xmltree::{Element, XMLNode};
+
+use crate::document_node::escape_field_name;
+
+/// Name of the element wrapping the top level JSON value
+pub const JSON_ROOT_ELEMENT: &str = "json";
+
+/// Name given to elements of a top level array, which have no key of their own
+pub const JSON_ARRAY_ITEM_ELEMENT: &str = "item";
+
+/// Maps a JSON value onto the element tree used by `DocumentNode`. Object keys
+/// holding scalars become fields of the element, escaped where they would
+/// clash with `tag` or `namespace`, keys holding objects become child
+/// elements, and arrays become sibling elements sharing the key's name.
+pub fn json_to_element(value: &Value) -> Element {
+    match value {
+        Value::Array(items) => {
+            let mut root = Element::new(JSON_ROOT_ELEMENT);
+
+            for item in items.iter() {
+                root.children.extend(
+                    value_to_nodes(JSON_ARRAY_ITEM_ELEMENT, item)
+                );
+            }
+
+            root
+        },
+        _ => {
+            let mut nodes = value_to_nodes(JSON_ROOT_ELEMENT, value);
+
+            match nodes.pop() {
+                Some(XMLNode::Element(element)) => element,
+                _ => Element::new(JSON_ROOT_ELEMENT),
+            }
+        }
+    }
+}
+
+fn value_to_nodes(name: &str, value: &Value) -> Vec<XMLNode> {
+    match value {
+        Value::Object(map) => {
+            let mut element = Element::new(name);
+
+            for (key, child) in map.iter() {
+                match child {
+                    Value::Object(_) | Value::Array(_) => {
+                        element.children.extend(value_to_nodes(key, child));
+                    },
+                    _ => {
+                        element.attributes.insert(escape_field_name(key), scalar_to_string(child));
+                    }
+                }
+            }
+
+            vec![XMLNode::Element(element)]
+        },
+        Value::Array(items) => {
+            items.iter()
+                .flat_map(|item| value_to_nodes(name, item))
+                .collect()
+        },
+        _ => {
+            let mut element = Element::new(name);
+            let text = scalar_to_string(value);
+
+            if !text.is_empty() {
+                element.children.push(XMLNode::Text(text));
+            }
+
+            vec![XMLNode::Element(element)]
+        }
+    }
+}
+
+fn scalar_to_string(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document_node::DocumentNode;
+
+    #[test]
+    fn keeps_keys_named_like_structural_fields() {
+        let value = serde_json::json!({
+            "releases": [{"tag": "v1.0", "_tag": "escaped", "namespace": "ns", "name": "first"}]
+        });
+        let element = json_to_element(&value);
+        let release = element.get_child("releases").unwrap();
+
+        let fields = DocumentNode::new(XMLNode::Element(release.clone())).get_fields();
+
+        assert_eq!(fields["tag"], "releases");
+        assert_eq!(fields["_tag"], "v1.0");
+        assert_eq!(fields["__tag"], "escaped");
+        assert_eq!(fields["_namespace"], "ns");
+        assert_eq!(fields["name"], "first");
+        assert!(!fields.contains_key("namespace"));
+    }
+}
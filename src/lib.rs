@@ -17,6 +17,8 @@ pub mod model;
 pub mod normalization;
 pub mod organization;
 pub mod profile;
+pub mod profile_matcher;
+pub mod profile_drift;
 pub mod provider;
 pub mod transformation;
 pub mod translation;
@@ -24,7 +26,17 @@ pub mod types;
 pub mod prelude;
 pub mod utility;
 pub mod json_node;
+pub mod json_document;
+pub mod xml_document;
+pub mod text_document;
+pub mod csv_document;
+pub mod encoding;
+pub mod provenance;
+pub mod page_metadata;
+pub mod structured_data;
 pub mod context;
 pub mod llm;
 pub mod traverse;
 pub mod meta_context;
+pub mod document_builder;
+pub mod sqlite_export;
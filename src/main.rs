@@ -1,4 +1,4 @@
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use std::collections::{HashMap, HashSet};
 use lazy_static::lazy_static;
 use atty::Stream;
@@ -10,6 +10,7 @@ use std::io::stdout;
 use fern::Dispatch;
 use async_trait::async_trait;
 use quick_js::{Context, JsValue};
+use futures::StreamExt;
 
 mod analysis;
 mod basis_network;
@@ -30,6 +31,8 @@ mod model;
 mod normalization;
 mod organization;
 mod profile;
+mod profile_matcher;
+mod profile_drift;
 mod provider;
 mod transformation;
 mod translation;
@@ -37,27 +40,37 @@ mod types;
 mod prelude;
 mod utility;
 mod json_node;
+mod json_document;
+mod xml_document;
+mod text_document;
+mod csv_document;
+mod encoding;
+mod provenance;
+mod page_metadata;
+mod structured_data;
 mod context;
 mod llm;
 mod traverse;
 mod meta_context;
+mod document_builder;
+mod sqlite_export;
 
 use crate::prelude::*;
-use crate::config::{CONFIG};
-use crate::provider::{YamlFileProvider};
+use crate::config::{CONFIG, ProviderType};
+use crate::provider::{Provider, get_provider};
 
-fn load_stdin() -> io::Result<String> {
+fn load_stdin() -> io::Result<Vec<u8>> {
     log::trace!("In load_stdin");
 
     if atty::is(Stream::Stdin) {
         return Err(io::Error::new(io::ErrorKind::Other, "stdin not redirected"));
     }
-    let mut buffer = String::new();
-    io::stdin().read_to_string(&mut buffer)?;
+    let mut buffer = Vec::new();
+    io::stdin().read_to_end(&mut buffer)?;
     return Ok(buffer);
 }
 
-fn init_logging() {
+fn init_logging(log_to_stderr: bool) {
     log::info!("Initializing logging...");
 
     let path = format!("{}/{}", read_lock!(CONFIG).dev.debug_dir, "debug.log");
@@ -66,19 +79,108 @@ fn init_logging() {
     Dispatch::new()
         .level(LevelFilter::Off)
         .level_for("parversion", LevelFilter::Trace)
-        .chain(stdout())
+        .chain(if log_to_stderr { fern::Output::stderr("\n") } else { fern::Output::stdout("\n") })
         .chain(log_file)
         .apply()
         .expect("Could not initialize logging");
 }
 
-fn setup() {
-    init_logging();
+fn setup(log_to_stderr: bool) {
+    init_logging(log_to_stderr);
+}
+
+async fn report_drift(
+    provider: Arc<dyn Provider>,
+    document: document::Document,
+    profile_id: Option<&str>,
+) -> Result<String, Errors> {
+    log::trace!("In report_drift");
+
+    let feature_paths = document.get_feature_paths()
+        .ok_or(Errors::UnexpectedDocumentType)?;
+
+    let profiles: Vec<profile::Profile> = provider.get_profiles().await?
+        .into_iter()
+        .filter(|profile| {
+            profile_id.map_or(true, |id| profile.id.to_string() == id)
+        })
+        .collect();
+
+    let reports = profile_drift::DriftReport::for_profiles(
+        &profiles,
+        &feature_paths,
+        &document.metadata.origin
+    );
+
+    match reports.first() {
+        Some(report) => Ok(report.to_string()),
+        None => Ok(String::from("No profiles to compare against\n")),
+    }
+}
+
+/// Reads the document from stdin, a file or a URL, exiting on failure
+async fn read_document(
+    matches: &clap::ArgMatches,
+    options: &Option<Options>,
+) -> document::Document {
+    let document = if let Ok(stdin) = load_stdin() {
+        document::Document::from_bytes(&stdin, options)
+    } else if let Some(path) = matches.value_of("file") {
+        let bytes = get_file_as_bytes(path).unwrap_or_else(|err| {
+            eprintln!("Failed to read file: {:?}", err);
+            std::process::exit(1);
+        });
+
+        document::Document::from_bytes(&bytes, options)
+    } else if let Some(url) = matches.value_of("url") {
+        let text = fetch_url_as_text(url).await.unwrap_or_else(|err| {
+            eprintln!("Failed to fetch URL: {:?}", err);
+            std::process::exit(1);
+        });
+
+        document::Document::from_string(text, &Options::with_origin(options, url))
+    } else {
+        eprintln!("No valid input provided. Please provide either stdin, a file or URL.");
+        std::process::exit(1);
+    };
+
+    document.unwrap_or_else(|err| {
+        eprintln!("Failed to read document: {:?}", err);
+        std::process::exit(1);
+    })
+}
+
+/// Streams records to stdout as newline delimited JSON, flushing after each
+/// one so that downstream tools see records as soon as they are written
+async fn write_ndjson(
+    provider: Arc<dyn Provider>,
+    document: document::Document,
+    document_format: &document_format::DocumentFormat,
+) -> Result<(), Errors> {
+    log::trace!("In write_ndjson");
+
+    let records = organization::organize_to_stream(provider, document);
+    futures::pin_mut!(records);
+
+    let mut stdout = stdout();
+
+    while let Some(record) = records.next().await {
+        let record = record?;
+        let line = document_builder::get_ndjson_line(&record, document_format)?;
+
+        stdout.write_all(line.as_bytes())
+            .and_then(|_| stdout.flush())
+            .map_err(|err| {
+                log::error!("Could not write record: {}", err);
+                Errors::FileOutputError
+            })?;
+    }
+
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() {
-    setup();
 
     let matches = App::new("parversion")
         .arg(Arg::with_name("file")
@@ -91,28 +193,124 @@ async fn main() {
             .long("url")
             .value_name("URL")
             .help("Provide url as document for processing"))
+        .arg(Arg::with_name("document-type")
+            .short('t')
+            .long("document-type")
+            .value_name("TYPE")
+            .help("Parse the document as json, xml, html, csv or text instead of sniffing its type"))
+        .arg(Arg::with_name("encoding")
+            .short('e')
+            .long("encoding")
+            .value_name("ENCODING")
+            .help("Decode the document with this encoding instead of detecting it"))
+        .arg(Arg::with_name("provider")
+            .short('p')
+            .long("provider")
+            .value_name("PROVIDER")
+            .help("Provider to use: yaml, json, sqlite or void"))
+        .arg(Arg::with_name("provider-path")
+            .long("provider-path")
+            .value_name("PATH")
+            .help("Path to the provider file or database"))
+        .arg(Arg::with_name("drift")
+            .long("drift")
+            .help("Report how the document's features differ from the closest profile"))
+        .arg(Arg::with_name("ndjson")
+            .long("ndjson")
+            .help("Write each record to stdout as a line of JSON as soon as it is built"))
+        .arg(Arg::with_name("profile")
+            .long("profile")
+            .value_name("PROFILE_ID")
+            .help("Profile to compare against when reporting drift"))
         .get_matches();
 
+    // Records own stdout when streaming, so logs go to stderr instead
+    setup(matches.is_present("ndjson"));
+
     let document_format = document_format::DocumentFormat::default();
 
-    let provider = Arc::new(YamlFileProvider::new(String::from("provider.yaml")));
+    let provider: Arc<dyn Provider> = {
+        let (configured_type, configured_path) = {
+            let config = read_lock!(CONFIG);
+            (config.provider.provider_type.clone(), config.provider.path.clone())
+        };
+
+        let provider_type = match matches.value_of("provider") {
+            Some(value) => value.parse::<ProviderType>().unwrap_or_else(|err| {
+                eprintln!("{}. Expected one of yaml, json, sqlite or void.", err);
+                std::process::exit(1);
+            }),
+            None => configured_type.clone(),
+        };
 
-    log::info!("Using yaml file provider");
+        let path = match matches.value_of("provider-path") {
+            Some(path) => path.to_string(),
+            None if provider_type == configured_type => configured_path,
+            None => provider_type.default_path(),
+        };
+
+        log::info!("Using {:?} provider at {}", provider_type, path);
+
+        get_provider(&provider_type, &path)
+    };
+
+    let document_type = matches.value_of("document-type").map(|value| {
+        value.parse::<document::DocumentType>().unwrap_or_else(|err| {
+            eprintln!("{}. Expected one of json, xml, html, csv or text.", err);
+            std::process::exit(1);
+        })
+    });
 
     let options = Options {
+        document_type,
+        encoding: matches.value_of("encoding").map(|value| value.to_string()),
         ..Options::default()
     };
 
     log::debug!("options: {:?}", options);
 
+    if matches.is_present("drift") {
+        let document = read_document(&matches, &Some(options)).await;
+
+        match report_drift(provider, document, matches.value_of("profile")).await {
+            Ok(report) => {
+                print!("{}", report);
+                std::process::exit(0);
+            },
+            Err(err) => {
+                eprintln!("Failed to report profile drift: {:?}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if matches.is_present("ndjson") {
+        let options = Some(options);
+        let document = read_document(&matches, &options).await;
+
+        match write_ndjson(provider, document, &document_format).await {
+            Ok(()) => std::process::exit(0),
+            Err(err) => {
+                eprintln!("Failed to write records: {:?}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
     let document = {
         if let Ok(stdin) = load_stdin() {
             log::info!("Received data from stdin");
-            
-            match normalization::normalize_text_to_document(
+
+            let options = Some(options);
+            let document = document::Document::from_bytes(&stdin, &options).unwrap_or_else(|err| {
+                eprintln!("Failed to read document from stdin: {:?}", err);
+                std::process::exit(1);
+            });
+
+            match normalization::normalize_document(
                 provider.clone(),
-                stdin,
-                &Some(options),
+                document,
+                &options,
                 &Some(document_format),
             ).await {
                 Ok(document) => document,
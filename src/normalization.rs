@@ -8,7 +8,7 @@ use crate::model::{Model};
 use crate::provider::{Provider};
 use crate::traverse::{build_document_from_nodeset};
 
-pub async fn normalize<P: Provider>(
+pub async fn normalize<P: Provider + ?Sized>(
     provider: Arc<P>,
     nodeset: NodeSet,
     options: &Option<Options>,
@@ -24,7 +24,7 @@ pub async fn normalize<P: Provider>(
     //nodeset.transmute(&target_model.json_schema).await
 }
 
-pub async fn normalize_nodeset<P: Provider>(
+pub async fn normalize_nodeset<P: Provider + ?Sized>(
     provider: Arc<P>,
     nodeset: NodeSet,
     options: &Option<Options>,
@@ -34,7 +34,7 @@ pub async fn normalize_nodeset<P: Provider>(
     normalize(Arc::clone(&provider), nodeset, options).await
 }
 
-pub async fn normalize_text_to_nodeset<P: Provider>(
+pub async fn normalize_text_to_nodeset<P: Provider + ?Sized>(
     provider: Arc<P>,
     text: String,
     options: &Option<Options>,
@@ -47,7 +47,7 @@ pub async fn normalize_text_to_nodeset<P: Provider>(
     normalize_nodeset(Arc::clone(&provider), nodeset, options).await
 }
 
-pub async fn normalize_text_to_document<P: Provider>(
+pub async fn normalize_text_to_document<P: Provider + ?Sized>(
     provider: Arc<P>,
     text: String,
     options: &Option<Options>,
@@ -64,7 +64,7 @@ pub async fn normalize_text_to_document<P: Provider>(
     )
 }
 
-pub async fn normalize_text<P: Provider>(
+pub async fn normalize_text<P: Provider + ?Sized>(
     provider: Arc<P>,
     text: String,
     options: &Option<Options>,
@@ -82,7 +82,7 @@ pub async fn normalize_text<P: Provider>(
     Ok(document.to_string())
 }
 
-pub async fn normalize_document_to_nodeset<P: Provider>(
+pub async fn normalize_document_to_nodeset<P: Provider + ?Sized>(
     provider: Arc<P>,
     document: Document,
     options: &Option<Options>,
@@ -94,7 +94,7 @@ pub async fn normalize_document_to_nodeset<P: Provider>(
     normalize_nodeset(Arc::clone(&provider), nodeset, options).await
 }
 
-pub async fn normalize_document<P: Provider>(
+pub async fn normalize_document<P: Provider + ?Sized>(
     provider: Arc<P>,
     document: Document,
     options: &Option<Options>,
@@ -111,7 +111,7 @@ pub async fn normalize_document<P: Provider>(
     )
 }
 
-pub async fn normalize_document_to_text<P: Provider>(
+pub async fn normalize_document_to_text<P: Provider + ?Sized>(
     provider: Arc<P>,
     document: Document,
     options: &Option<Options>,
@@ -124,7 +124,7 @@ pub async fn normalize_document_to_text<P: Provider>(
     Ok(document.to_string())
 }
 
-pub async fn normalize_file_to_nodeset<P: Provider>(
+pub async fn normalize_file_to_nodeset<P: Provider + ?Sized>(
     provider: Arc<P>,
     path: &str,
     options: &Option<Options>,
@@ -132,12 +132,13 @@ pub async fn normalize_file_to_nodeset<P: Provider>(
     log::trace!("In normalize_file_to_nodeset");
     log::debug!("file path: {}", path);
 
-    let text = get_file_as_text(path)?;
+    let bytes = get_file_as_bytes(path)?;
+    let document = Document::from_bytes(&bytes, options)?;
 
-    normalize_text_to_nodeset(Arc::clone(&provider), text, options).await
+    normalize_document_to_nodeset(Arc::clone(&provider), document, options).await
 }
 
-pub async fn normalize_file_to_document<P: Provider>(
+pub async fn normalize_file_to_document<P: Provider + ?Sized>(
     provider: Arc<P>,
     path: &str,
     options: &Option<Options>,
@@ -155,7 +156,7 @@ pub async fn normalize_file_to_document<P: Provider>(
     )
 }
 
-pub async fn normalize_file_to_text<P: Provider>(
+pub async fn normalize_file_to_text<P: Provider + ?Sized>(
     provider: Arc<P>,
     path: &str,
     options: &Option<Options>,
@@ -169,7 +170,7 @@ pub async fn normalize_file_to_text<P: Provider>(
     Ok(document.to_string())
 }
 
-pub async fn normalize_file<P: Provider>(
+pub async fn normalize_file<P: Provider + ?Sized>(
     provider: Arc<P>,
     path: &str,
     options: &Option<Options>,
@@ -187,7 +188,7 @@ pub async fn normalize_file<P: Provider>(
     })
 }
 
-pub async fn normalize_url_to_nodeset<P: Provider>(
+pub async fn normalize_url_to_nodeset<P: Provider + ?Sized>(
     provider: Arc<P>,
     url: &str,
     options: &Option<Options>,
@@ -196,11 +197,12 @@ pub async fn normalize_url_to_nodeset<P: Provider>(
     log::debug!("URL: {}", url);
 
     let text = fetch_url_as_text(url).await?;
+    let options = Options::with_origin(options, url);
 
-    normalize_text_to_nodeset(Arc::clone(&provider), text, options).await
+    normalize_text_to_nodeset(Arc::clone(&provider), text, &options).await
 }
 
-pub async fn normalize_url_to_document<P: Provider>(
+pub async fn normalize_url_to_document<P: Provider + ?Sized>(
     provider: Arc<P>,
     url: &str,
     options: &Option<Options>,
@@ -218,7 +220,7 @@ pub async fn normalize_url_to_document<P: Provider>(
     )
 }
 
-pub async fn normalize_url_to_text<P: Provider>(
+pub async fn normalize_url_to_text<P: Provider + ?Sized>(
     provider: Arc<P>,
     url: &str,
     options: &Option<Options>,
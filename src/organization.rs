@@ -1,17 +1,22 @@
 use std::sync::Arc;
+use futures::stream::{self, Stream};
+use tokio::sync::mpsc;
+use tokio::task;
 
 use crate::prelude::*;
+use crate::data_node::DataNode;
 use crate::document::{Document};
 use crate::document_format::{DocumentFormat};
 use crate::provider::Provider;
 use crate::traverse::{
     TraversalWithContext,
     traverse_with_context,
+    traverse_with_context_each,
     build_document_from_nodeset
 };
 use crate::analysis::{Analysis};
 
-pub async fn organize<P: Provider>(
+pub async fn organize<P: Provider + ?Sized>(
     provider: Arc<P>,
     document: Document,
     options: &Option<Options>,
@@ -41,7 +46,7 @@ pub async fn organize<P: Provider>(
     Ok(nodeset)
 }
 
-pub async fn organize_document<P: Provider>(
+pub async fn organize_document<P: Provider + ?Sized>(
     provider: Arc<P>,
     document: Document,
     options: &Option<Options>,
@@ -62,7 +67,7 @@ pub async fn organize_document<P: Provider>(
     )
 }
 
-pub async fn organize_document_to_string<P: Provider>(
+pub async fn organize_document_to_string<P: Provider + ?Sized>(
     provider: Arc<P>,
     document: Document,
     options: &Option<Options>,
@@ -80,7 +85,7 @@ pub async fn organize_document_to_string<P: Provider>(
     Ok(document.to_string())
 }
 
-pub async fn organize_text<P: Provider>(
+pub async fn organize_text<P: Provider + ?Sized>(
     provider: Arc<P>,
     text: String,
     options: &Option<Options>,
@@ -92,7 +97,7 @@ pub async fn organize_text<P: Provider>(
     organize(Arc::clone(&provider), document, options).await
 }
 
-pub async fn organize_text_to_document<P: Provider>(
+pub async fn organize_text_to_document<P: Provider + ?Sized>(
     provider: Arc<P>,
     text: String,
     options: &Option<Options>,
@@ -105,7 +110,7 @@ pub async fn organize_text_to_document<P: Provider>(
     build_document_from_nodeset(provider, nodeset, document_format)
 }
 
-pub async fn organize_file<P: Provider>(
+pub async fn organize_file<P: Provider + ?Sized>(
     provider: Arc<P>,
     path: &str,
     options: &Option<Options>,
@@ -113,15 +118,16 @@ pub async fn organize_file<P: Provider>(
     log::trace!("In organize_file");
     log::debug!("file path: {}", path);
 
-    let text = get_file_as_text(path).map_err(|err| {
-        log::error!("Failed to get file as text: {:?}", err);
+    let bytes = get_file_as_bytes(path).map_err(|err| {
+        log::error!("Failed to get file as bytes: {:?}", err);
         Errors::FileInputError
     })?;
+    let document = Document::from_bytes(&bytes, options)?;
 
-    organize_text(Arc::clone(&provider), text, options).await
+    organize(Arc::clone(&provider), document, options).await
 }
 
-pub async fn organize_file_to_document<P: Provider>(
+pub async fn organize_file_to_document<P: Provider + ?Sized>(
     provider: Arc<P>,
     path: &str,
     options: &Option<Options>,
@@ -135,7 +141,7 @@ pub async fn organize_file_to_document<P: Provider>(
     build_document_from_nodeset(provider, nodeset, document_format)
 }
 
-pub async fn organize_file_to_string<P: Provider>(
+pub async fn organize_file_to_string<P: Provider + ?Sized>(
     provider: Arc<P>,
     path: &str,
     options: &Option<Options>,
@@ -148,3 +154,110 @@ pub async fn organize_file_to_string<P: Provider>(
 
     Ok(document.to_string())
 }
+
+/// Records the traversal may get ahead of the consumer by
+const RECORD_CHANNEL_CAPACITY: usize = 64;
+
+enum RecordStreamState<P: Provider + ?Sized> {
+    Pending(Box<(Arc<P>, Document)>),
+    Streaming(mpsc::Receiver<Result<Arc<DataNode>, Errors>>),
+    Done,
+}
+
+/// Yields the records of a document in document order as the traversal
+/// builds them, so consumers can start on them before the whole node set
+/// exists. The traversal runs on a blocking thread and waits when the
+/// consumer falls behind. Records aren't analysed, as `Analysis::start`,
+/// which `organize` runs over the finished traversal, isn't implemented yet.
+/// Errors end the stream.
+pub fn organize_to_stream<P: Provider + ?Sized>(
+    provider: Arc<P>,
+    document: Document,
+) -> impl Stream<Item = Result<Arc<DataNode>, Errors>> {
+    log::trace!("In organize_to_stream");
+
+    let state = RecordStreamState::Pending(Box::new((provider, document)));
+
+    stream::unfold(state, |state| async move {
+        let mut receiver = match state {
+            RecordStreamState::Pending(pending) => {
+                let (provider, mut document) = *pending;
+
+                let profile = match document.perform_analysis(provider).await {
+                    Ok(profile) => profile,
+                    Err(err) => return Some((Err(err), RecordStreamState::Done)),
+                };
+
+                let (sender, receiver) = mpsc::channel(RECORD_CHANNEL_CAPACITY);
+
+                task::spawn_blocking(move || {
+                    let result = traverse_with_context_each(&profile, document, &mut |data_node| {
+                        // A closed channel means the consumer stopped listening
+                        let _ = sender.blocking_send(Ok(Arc::clone(data_node)));
+                    });
+
+                    if let Err(err) = result {
+                        let _ = sender.blocking_send(Err(err));
+                    }
+                });
+
+                receiver
+            },
+            RecordStreamState::Streaming(receiver) => receiver,
+            RecordStreamState::Done => return None,
+        };
+
+        match receiver.recv().await {
+            Some(Ok(record)) => Some((Ok(record), RecordStreamState::Streaming(receiver))),
+            Some(Err(err)) => Some((Err(err), RecordStreamState::Done)),
+            None => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use crate::document::DocumentType;
+    use crate::provider::VoidProvider;
+
+    #[tokio::test]
+    async fn streams_the_records_of_the_traversal_in_order() {
+        let items: String = (0..200).map(|index| format!("<li>item {}</li>", index)).collect();
+        let html = format!("<html><body><ul>{}</ul></body></html>", items);
+
+        let mut document = Document::from_string(html.clone(), &None).unwrap();
+        let profile = document.perform_analysis(Arc::new(VoidProvider)).await.unwrap();
+        let TraversalWithContext { nodeset, .. } = traverse_with_context(&profile, document).unwrap();
+
+        let document = Document::from_string(html, &None).unwrap();
+        let records: Vec<Arc<DataNode>> = organize_to_stream(Arc::new(VoidProvider), document)
+            .map(|record| record.unwrap())
+            .collect()
+            .await;
+
+        let get_fields = |data_nodes: &[Arc<DataNode>]| -> Vec<_> {
+            data_nodes.iter().map(|data_node| data_node.fields.clone()).collect()
+        };
+
+        assert!(records.len() > RECORD_CHANNEL_CAPACITY);
+        assert_eq!(get_fields(&records), get_fields(&nodeset.data_nodes));
+    }
+
+    #[tokio::test]
+    async fn ends_the_stream_on_an_error() {
+        let options = Some(Options {
+            document_type: Some(DocumentType::JSON),
+            ..Options::default()
+        });
+        let document = Document::from_string("not json".to_string(), &options).unwrap();
+
+        let records: Vec<Result<Arc<DataNode>, Errors>> = organize_to_stream(Arc::new(VoidProvider), document)
+            .collect()
+            .await;
+
+        assert_eq!(records.len(), 1);
+        assert!(records[0].is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use std::collections::HashMap;
+use url::Url;
+
+use crate::document::DocumentMetadata;
+
+/// Meta properties that hold the publication or modification date of a
+/// page, in order of preference
+const DATE_META_KEYS: [&str; 7] = [
+    "article:published_time",
+    "og:published_time",
+    "date",
+    "dc.date",
+    "dcterms.date",
+    "pubdate",
+    "article:modified_time",
+];
+
+/// Everything of interest found in the head of an HTML page
+#[derive(Debug, Default)]
+struct PageMetadata {
+    title: Option<String>,
+    language: Option<String>,
+    canonical_url: Option<String>,
+    base_url: Option<String>,
+    /// Content of `<meta>` tags keyed by lowercased `name` or `property`
+    meta: HashMap<String, String>,
+}
+
+/// Fills in document metadata from the page itself: title, canonical URL,
+/// language, description, `<base href>` and OpenGraph and Twitter card tags.
+/// An origin or date that wasn't given in the options is inferred from them.
+pub fn extract_page_metadata(dom: &RcDom, metadata: &mut DocumentMetadata) {
+    let mut page = PageMetadata::default();
+    collect_page_metadata(&dom.document, &mut page);
+
+    let get_meta = |key: &str| page.meta.get(key).cloned();
+
+    let base_url = page.base_url.clone()
+        .or_else(|| metadata.origin.clone())
+        .and_then(|base| Url::parse(&base).ok());
+
+    let resolve = |value: String| -> String {
+        match &base_url {
+            Some(base) => base.join(&value).map(|url| url.to_string()).unwrap_or(value),
+            None => value,
+        }
+    };
+
+    metadata.title = page.title.clone().or_else(|| get_meta("og:title"));
+    metadata.description = get_meta("description").or_else(|| get_meta("og:description"));
+    metadata.language = page.language.clone()
+        .or_else(|| get_meta("content-language"))
+        .or_else(|| get_meta("og:locale"));
+    metadata.base_url = page.base_url.clone();
+    metadata.canonical_url = page.canonical_url.clone()
+        .or_else(|| get_meta("og:url"))
+        .map(resolve);
+
+    metadata.open_graph = page.meta.iter()
+        .filter(|(key, _)| key.starts_with("og:"))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    metadata.twitter_card = page.meta.iter()
+        .filter(|(key, _)| key.starts_with("twitter:"))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+
+    if metadata.origin.is_none() {
+        metadata.origin = metadata.canonical_url.clone()
+            .or_else(|| metadata.base_url.clone())
+            .filter(|url| Url::parse(url).is_ok());
+
+        if let Some(origin) = &metadata.origin {
+            log::info!("Inferred document origin from page metadata: {}", origin);
+        }
+    }
+
+    if metadata.date.is_none() {
+        metadata.date = DATE_META_KEYS.iter().find_map(|key| get_meta(key));
+
+        if let Some(date) = &metadata.date {
+            log::info!("Inferred document date from page metadata: {}", date);
+        }
+    }
+}
+
+fn collect_page_metadata(node: &Handle, page: &mut PageMetadata) {
+    if let NodeData::Element { ref name, ref attrs, .. } = node.data {
+        let attrs = attrs.borrow();
+        let get_attr = |attr_name: &str| -> Option<String> {
+            attrs.iter()
+                .find(|attr| attr.name.local.as_ref().eq_ignore_ascii_case(attr_name))
+                .map(|attr| attr.value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        match name.local.as_ref() {
+            "html" if page.language.is_none() => {
+                page.language = get_attr("lang").or_else(|| get_attr("xml:lang"));
+            },
+            "title" if page.title.is_none() => {
+                page.title = Some(get_text(node)).filter(|title| !title.is_empty());
+            },
+            "base" if page.base_url.is_none() => {
+                page.base_url = get_attr("href");
+            },
+            "link" => {
+                let is_canonical = get_attr("rel").is_some_and(|rel| {
+                    rel.split_whitespace().any(|value| value.eq_ignore_ascii_case("canonical"))
+                });
+
+                if is_canonical && page.canonical_url.is_none() {
+                    page.canonical_url = get_attr("href");
+                }
+            },
+            "meta" => {
+                let key = get_attr("property")
+                    .or_else(|| get_attr("name"))
+                    .or_else(|| get_attr("http-equiv"));
+
+                if let (Some(key), Some(content)) = (key, get_attr("content")) {
+                    page.meta.entry(key.to_lowercase()).or_insert(content);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    for child in node.children.borrow().iter() {
+        collect_page_metadata(child, page);
+    }
+}
+
+/// The text content of a node with whitespace collapsed
+pub fn get_text(node: &Handle) -> String {
+    let mut text = String::new();
+
+    fn collect(node: &Handle, text: &mut String) {
+        if let NodeData::Text { ref contents } = node.data {
+            text.push_str(&contents.borrow());
+        }
+
+        for child in node.children.borrow().iter() {
+            collect(child, text);
+        }
+    }
+
+    collect(node, &mut text);
+
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
@@ -1,7 +1,10 @@
 use serde::{Serialize, Deserialize};
-use std::collections::{HashSet};
+use std::collections::{HashMap, HashSet};
+use regex::Regex;
+use url::Url;
 
 use crate::prelude::*;
+use crate::config::{CONFIG};
 use crate::transformation::{
     XMLElementTransformation,
     HashTransformation
@@ -15,26 +18,231 @@ pub struct Profile {
     pub xml_element_transformation: Option<XMLElementTransformation>,
     pub hash_transformation: Option<HashTransformation>,
     pub meaningful_fields: Option<Vec<String>>,
+    #[serde(default)]
+    pub origin_patterns: Option<Vec<OriginPattern>>,
+    #[serde(default)]
+    pub feature_paths: Option<Vec<String>>,
+    /// Keeps comments, including conditional comments, as nodes of their own
+    /// so sites that put data in them can be processed. Comments never count
+    /// as features, since profiles are matched before this is known.
+    #[serde(default)]
+    pub retain_comments: bool,
+}
+
+/// Regular expressions checked against the origin of a document. Every
+/// pattern that is present must match for the origin to match.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct OriginPattern {
+    pub url: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+}
+
+impl OriginPattern {
+    pub fn from_origin(origin: &str) -> Option<Self> {
+        let url = Url::parse(origin).ok()?;
+        let host = url.host_str()?;
+
+        Some(OriginPattern {
+            url: None,
+            host: Some(format!("^{}$", regex::escape(host))),
+            path: None,
+        })
+    }
+
+    /// The one host the pattern can match, when its host pattern is an
+    /// anchored literal such as those made by `from_origin`
+    pub fn get_literal_host(&self) -> Option<String> {
+        let pattern = self.host.as_ref()?.strip_prefix('^')?.strip_suffix('$')?;
+        let mut host = String::new();
+        let mut chars = pattern.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                // Escaped letters and digits are classes such as \d
+                '\\' => match chars.next()? {
+                    escaped if escaped.is_ascii_alphanumeric() => return None,
+                    escaped => host.push(escaped),
+                },
+                '.' | '^' | '$' | '*' | '+' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '|' => return None,
+                c => host.push(c),
+            }
+        }
+
+        Some(host)
+    }
+
+    pub fn matches(&self, origin: &str) -> bool {
+        let parsed = Url::parse(origin).ok();
+
+        let host = parsed.as_ref().and_then(|url| url.host_str()).unwrap_or("");
+        let path = parsed.as_ref().map(|url| url.path()).unwrap_or(origin);
+
+        [
+            (&self.url, origin),
+            (&self.host, host),
+            (&self.path, path),
+        ].iter().all(|(pattern, value)| {
+            match pattern {
+                Some(pattern) => match Regex::new(pattern) {
+                    Ok(regex) => regex.is_match(value),
+                    Err(err) => {
+                        log::warn!("Invalid origin pattern {}: {}", pattern, err);
+                        false
+                    }
+                },
+                None => true,
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProfileMatch {
+    pub profile: Profile,
+    pub score: f64,
+    pub origin_match: bool,
 }
 
 impl Profile {
+    /// Creates a profile for a document that no existing profile matched,
+    /// using the default hash transformation
+    pub fn from_feature_paths(
+        feature_paths: &HashSet<String>,
+        description: String,
+        origin: &Option<String>,
+        xml_element_transformation: XMLElementTransformation,
+    ) -> Self {
+        let mut sorted_paths: Vec<String> = feature_paths.iter().cloned().collect();
+        sorted_paths.sort();
+
+        let origin_patterns = origin.as_ref()
+            .and_then(|origin| OriginPattern::from_origin(origin))
+            .map(|pattern| vec![pattern]);
+
+        Profile {
+            id: ID::new(),
+            description,
+            features: hash_features(feature_paths),
+            xml_element_transformation: Some(xml_element_transformation),
+            hash_transformation: Some(HashTransformation::default()),
+            meaningful_fields: Some(vec![
+                String::from("text"),
+                String::from("href"),
+                String::from("title"),
+            ]),
+            origin_patterns,
+            feature_paths: Some(sorted_paths),
+            retain_comments: false,
+        }
+    }
+
+    /// Maps feature hashes back to the paths they were computed from, for
+    /// the features whose paths the profile recorded
+    pub fn get_feature_path_lookup(&self) -> HashMap<Hash, String> {
+        self.feature_paths
+            .iter()
+            .flatten()
+            .map(|path| (hash_feature(path), path.clone()))
+            .collect()
+    }
+
+    pub fn matches_origin(&self, origin: &Option<String>) -> bool {
+        match (&self.origin_patterns, origin) {
+            (Some(patterns), Some(origin)) => {
+                patterns.iter().any(|pattern| pattern.matches(origin))
+            },
+            _ => false,
+        }
+    }
+
+    /// Scores the profile against the features, accepting a lower similarity
+    /// when one of its origin patterns matches
+    pub fn get_match(
+        &self,
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
+        threshold: f64,
+    ) -> Option<ProfileMatch> {
+        let score = jaccard_similarity(features, &self.features);
+        let origin_match = self.matches_origin(origin);
+
+        log::debug!("similarity: {}, origin match: {}", score, origin_match);
+
+        let threshold = if origin_match {
+            threshold.min(get_origin_similarity_threshold())
+        } else {
+            threshold
+        };
+
+        if score >= threshold {
+            Some(ProfileMatch {
+                profile: self.clone(),
+                score,
+                origin_match,
+            })
+        } else {
+            None
+        }
+    }
+
     pub fn get_similar_profile(
         profiles: &Vec<Profile>,
-        features: &HashSet<Hash>
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
     ) -> Option<Profile> {
-        profiles.iter()
-            .find(|profile| {
-                let similarity = jaccard_similarity(features, &profile.features);
+        Profile::get_ranked_profiles(profiles, features, origin, get_similarity_threshold())
+            .into_iter()
+            .next()
+            .map(|profile_match| profile_match.profile)
+    }
 
-                log::debug!("similarity: {}", similarity);
+    /// Scores every profile against the features and returns those at or above
+    /// the threshold, best match first
+    pub fn get_ranked_profiles(
+        profiles: &[Profile],
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
+        threshold: f64,
+    ) -> Vec<ProfileMatch> {
+        let mut matches: Vec<ProfileMatch> = profiles.iter()
+            .filter_map(|profile| profile.get_match(features, origin, threshold))
+            .collect();
 
-                similarity > 0.8
-            })
-            .map(|profile| profile.clone())
+        sort_profile_matches(&mut matches);
+
+        matches
     }
 }
 
-fn jaccard_similarity(set_a: &HashSet<Hash>, set_b: &HashSet<Hash>) -> f64 {
+pub fn hash_feature(path: &str) -> Hash {
+    let mut hash = Hash::new();
+    hash.push(path).finalize().clear_items();
+    hash.clone()
+}
+
+pub fn hash_features(paths: &HashSet<String>) -> HashSet<Hash> {
+    paths.iter().map(|path| hash_feature(path)).collect()
+}
+
+pub fn get_similarity_threshold() -> f64 {
+    read_lock!(CONFIG).profile.similarity_threshold
+}
+
+pub fn get_origin_similarity_threshold() -> f64 {
+    read_lock!(CONFIG).profile.origin_similarity_threshold
+}
+
+/// Orders matches by score, preferring origin matches when scores are tied
+pub fn sort_profile_matches(matches: &mut [ProfileMatch]) {
+    matches.sort_by(|a, b| {
+        b.score.partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(b.origin_match.cmp(&a.origin_match))
+    });
+}
+
+pub fn jaccard_similarity(set_a: &HashSet<Hash>, set_b: &HashSet<Hash>) -> f64 {
     let intersection: HashSet<_> = set_a.intersection(set_b).collect();
     let union: HashSet<_> = set_a.union(set_b).collect();
 
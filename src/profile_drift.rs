@@ -0,0 +1,128 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::prelude::*;
+use crate::profile::{
+    Profile,
+    hash_feature,
+    jaccard_similarity,
+    get_similarity_threshold,
+    get_origin_similarity_threshold
+};
+
+/// Explains how a document's structural features differ from a profile's
+#[derive(Clone, Debug)]
+pub struct DriftReport {
+    pub profile_id: ID,
+    pub profile_description: String,
+    pub score: f64,
+    pub threshold: f64,
+    pub origin_match: bool,
+    pub is_match: bool,
+    /// Feature paths present in the document but not in the profile
+    pub added: Vec<String>,
+    /// Features present in the profile but not in the document. These are
+    /// unhashed paths when the profile recorded them, otherwise hashes.
+    pub removed: Vec<String>,
+    pub unchanged: usize,
+}
+
+impl DriftReport {
+    pub fn new(
+        profile: &Profile,
+        feature_paths: &HashSet<String>,
+        origin: &Option<String>,
+    ) -> Self {
+        let document_features: HashSet<Hash> = feature_paths
+            .iter()
+            .map(|path| hash_feature(path))
+            .collect();
+
+        let mut added: Vec<String> = feature_paths
+            .iter()
+            .filter(|path| !profile.features.contains(&hash_feature(path)))
+            .cloned()
+            .collect();
+        added.sort();
+
+        let path_lookup = profile.get_feature_path_lookup();
+
+        let mut removed: Vec<String> = profile.features
+            .difference(&document_features)
+            .map(|hash| {
+                path_lookup.get(hash)
+                    .cloned()
+                    .unwrap_or_else(|| hash.to_string().unwrap_or_default())
+            })
+            .collect();
+        removed.sort();
+
+        let unchanged = profile.features.intersection(&document_features).count();
+
+        let score = jaccard_similarity(&document_features, &profile.features);
+        let origin_match = profile.matches_origin(origin);
+
+        let threshold = if origin_match {
+            get_similarity_threshold().min(get_origin_similarity_threshold())
+        } else {
+            get_similarity_threshold()
+        };
+
+        DriftReport {
+            profile_id: profile.id.clone(),
+            profile_description: profile.description.clone(),
+            score,
+            threshold,
+            origin_match,
+            is_match: score >= threshold,
+            added,
+            removed,
+            unchanged,
+        }
+    }
+
+    /// Builds a report for every profile, closest profile first
+    pub fn for_profiles(
+        profiles: &[Profile],
+        feature_paths: &HashSet<String>,
+        origin: &Option<String>,
+    ) -> Vec<Self> {
+        let mut reports: Vec<DriftReport> = profiles
+            .iter()
+            .map(|profile| DriftReport::new(profile, feature_paths, origin))
+            .collect();
+
+        reports.sort_by(|a, b| {
+            b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        reports
+    }
+}
+
+impl fmt::Display for DriftReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Profile {} ({})", self.profile_id.to_string(), self.profile_description)?;
+        writeln!(
+            f,
+            "Similarity: {:.3} (threshold {:.3}, origin match: {}) => {}",
+            self.score,
+            self.threshold,
+            if self.origin_match { "yes" } else { "no" },
+            if self.is_match { "match" } else { "no match" }
+        )?;
+        writeln!(f, "Unchanged features: {}", self.unchanged)?;
+
+        writeln!(f, "Added features ({}):", self.added.len())?;
+        for path in self.added.iter() {
+            writeln!(f, "  + {}", path)?;
+        }
+
+        writeln!(f, "Removed features ({}):", self.removed.len())?;
+        for path in self.removed.iter() {
+            writeln!(f, "  - {}", path)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::prelude::*;
+use crate::profile::{
+    Profile,
+    ProfileMatch,
+    sort_profile_matches
+};
+
+/// Number of MinHash functions in a signature
+pub const SIGNATURE_LENGTH: usize = 128;
+
+/// Signatures are split into this many bands for locality sensitive hashing.
+/// With 32 bands of 4 rows, profiles with a Jaccard similarity of 0.8 share
+/// a bucket with near certainty while dissimilar profiles rarely do.
+pub const BAND_COUNT: usize = 32;
+
+const ROWS_PER_BAND: usize = SIGNATURE_LENGTH / BAND_COUNT;
+
+pub type Signature = Vec<u64>;
+
+/// Finds profiles similar to a feature set without scoring every profile.
+/// Only profiles sharing at least one LSH bucket with the query are scored
+/// with an exact Jaccard similarity.
+#[derive(Clone, Debug)]
+pub struct ProfileMatcher {
+    threshold: f64,
+    profiles: Vec<Profile>,
+    buckets: HashMap<(usize, u64), HashSet<usize>>,
+    with_origin_patterns: HashSet<usize>,
+}
+
+impl ProfileMatcher {
+    pub fn new(threshold: f64) -> Self {
+        ProfileMatcher {
+            threshold,
+            profiles: Vec::new(),
+            buckets: HashMap::new(),
+            with_origin_patterns: HashSet::new(),
+        }
+    }
+
+    pub fn from_profiles(profiles: &[Profile], threshold: f64) -> Self {
+        let mut matcher = ProfileMatcher::new(threshold);
+
+        for profile in profiles.iter() {
+            matcher.insert(profile.clone());
+        }
+
+        matcher
+    }
+
+    pub fn set_threshold(&mut self, threshold: f64) {
+        self.threshold = threshold;
+    }
+
+    pub fn len(&self) -> usize {
+        self.profiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+
+    pub fn insert(&mut self, profile: Profile) {
+        let index = match self.profiles.iter().position(|p| p.id == profile.id) {
+            Some(index) => {
+                for members in self.buckets.values_mut() {
+                    members.remove(&index);
+                }
+                self.profiles[index] = profile;
+                index
+            },
+            None => {
+                self.profiles.push(profile);
+                self.profiles.len() - 1
+            }
+        };
+
+        if self.profiles[index].origin_patterns.is_some() {
+            self.with_origin_patterns.insert(index);
+        } else {
+            self.with_origin_patterns.remove(&index);
+        }
+
+        let signature = get_signature(&self.profiles[index].features);
+
+        for (band, key) in get_band_keys(&signature).into_iter().enumerate() {
+            self.buckets.entry((band, key)).or_default().insert(index);
+        }
+    }
+
+    /// Returns profiles at or above the threshold, best match first. Profiles
+    /// whose origin patterns match the origin are always scored since a
+    /// matching origin lowers the similarity they need.
+    pub fn get_candidates(
+        &self,
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
+    ) -> Vec<ProfileMatch> {
+        let signature = get_signature(features);
+
+        let mut candidate_indices: HashSet<usize> = get_band_keys(&signature)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(band, key)| self.buckets.get(&(band, key)))
+            .flat_map(|members| members.iter().cloned())
+            .collect();
+
+        if origin.is_some() {
+            candidate_indices.extend(
+                self.with_origin_patterns
+                    .iter()
+                    .filter(|index| self.profiles[**index].matches_origin(origin))
+                    .cloned()
+            );
+        }
+
+        log::debug!(
+            "LSH narrowed {} profiles to {} candidates",
+            self.profiles.len(),
+            candidate_indices.len()
+        );
+
+        let mut matches: Vec<ProfileMatch> = candidate_indices
+            .into_iter()
+            .filter_map(|index| {
+                self.profiles[index].get_match(features, origin, self.threshold)
+            })
+            .collect();
+
+        sort_profile_matches(&mut matches);
+
+        matches
+    }
+
+    pub fn get_best_match(
+        &self,
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
+    ) -> Option<ProfileMatch> {
+        self.get_candidates(features, origin).into_iter().next()
+    }
+}
+
+pub fn get_signature(features: &HashSet<Hash>) -> Signature {
+    let feature_values: Vec<u64> = features
+        .iter()
+        .map(|feature| fnv1a(feature.to_string().unwrap_or_default().as_bytes()))
+        .collect();
+
+    (0..SIGNATURE_LENGTH)
+        .map(|index| {
+            let seed = splitmix64(index as u64);
+
+            feature_values
+                .iter()
+                .map(|value| splitmix64(value ^ seed))
+                .min()
+                .unwrap_or(u64::MAX)
+        })
+        .collect()
+}
+
+/// Collapses each band of the signature into a single bucket key
+pub fn get_band_keys(signature: &Signature) -> Vec<u64> {
+    signature
+        .chunks(ROWS_PER_BAND)
+        .map(|rows| {
+            let bytes: Vec<u8> = rows.iter().flat_map(|row| row.to_le_bytes()).collect();
+            fnv1a(&bytes)
+        })
+        .collect()
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    hash
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::OriginPattern;
+
+    fn get_features(values: &[&str]) -> HashSet<Hash> {
+        values.iter().map(|value| Hash::from_str(value)).collect()
+    }
+
+    fn get_numbered_features(range: std::ops::Range<usize>) -> HashSet<Hash> {
+        range.map(|index| Hash::from_str(&format!("feature-{}", index))).collect()
+    }
+
+    fn get_profile(features: HashSet<Hash>) -> Profile {
+        Profile {
+            id: ID::new(),
+            description: String::from("test profile"),
+            features,
+            xml_element_transformation: None,
+            hash_transformation: None,
+            meaningful_fields: None,
+            origin_patterns: None,
+            feature_paths: None,
+            retain_comments: false,
+        }
+    }
+
+    fn share_bucket(first: &HashSet<Hash>, second: &HashSet<Hash>) -> bool {
+        get_band_keys(&get_signature(first))
+            .into_iter()
+            .zip(get_band_keys(&get_signature(second)))
+            .any(|(first, second)| first == second)
+    }
+
+    /// Band keys are stored by the sqlite provider, so they must not change
+    /// between runs or versions
+    const STABLE_FIRST_BAND_KEY: u64 = 11541369669978988406;
+
+    #[test]
+    fn signature_is_stable() {
+        let features = get_features(&["a", "b", "c"]);
+        let signature = get_signature(&features);
+
+        assert_eq!(signature.len(), SIGNATURE_LENGTH);
+        assert_eq!(signature, get_signature(&get_features(&["c", "b", "a"])));
+        assert_eq!(get_band_keys(&signature).len(), BAND_COUNT);
+        assert_eq!(get_band_keys(&signature)[0], STABLE_FIRST_BAND_KEY);
+    }
+
+    #[test]
+    fn empty_feature_set_has_a_signature() {
+        let signature = get_signature(&HashSet::new());
+
+        assert!(signature.iter().all(|value| *value == u64::MAX));
+    }
+
+    #[test]
+    fn similar_feature_sets_share_a_bucket() {
+        let features = get_numbered_features(0..20);
+        let similar = get_numbered_features(1..21);
+
+        assert!(share_bucket(&features, &similar));
+    }
+
+    #[test]
+    fn disjoint_feature_sets_share_no_bucket() {
+        let features = get_numbered_features(0..20);
+        let disjoint = get_numbered_features(100..120);
+
+        assert!(!share_bucket(&features, &disjoint));
+    }
+
+    #[test]
+    fn candidates_are_limited_to_similar_profiles() {
+        let similar = get_profile(get_numbered_features(1..21));
+        let disjoint = get_profile(get_numbered_features(100..120));
+
+        let matcher = ProfileMatcher::from_profiles(&[similar.clone(), disjoint], 0.8);
+        let candidates = matcher.get_candidates(&get_numbered_features(0..20), &None);
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].profile.id, similar.id);
+    }
+
+    #[test]
+    fn reinserting_a_profile_replaces_its_buckets() {
+        let mut profile = get_profile(get_numbered_features(0..20));
+        let mut matcher = ProfileMatcher::from_profiles(&[profile.clone()], 0.8);
+
+        profile.features = get_numbered_features(100..120);
+        matcher.insert(profile);
+
+        assert_eq!(matcher.len(), 1);
+        assert!(matcher.get_candidates(&get_numbered_features(0..20), &None).is_empty());
+        assert_eq!(matcher.get_candidates(&get_numbered_features(100..120), &None).len(), 1);
+    }
+
+    #[test]
+    fn only_profiles_matching_the_origin_skip_lsh() {
+        let mut matching = get_profile(get_numbered_features(0..10));
+        matching.origin_patterns = OriginPattern::from_origin("https://example.com/").map(|pattern| vec![pattern]);
+
+        let mut other = get_profile(get_numbered_features(5..15));
+        other.origin_patterns = OriginPattern::from_origin("https://example.org/").map(|pattern| vec![pattern]);
+
+        let matcher = ProfileMatcher::from_profiles(&[matching.clone(), other], 0.0);
+        let origin = Some(String::from("https://example.com/news"));
+        let candidates = matcher.get_candidates(&get_numbered_features(100..120), &origin);
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].profile.id, matching.id);
+        assert!(candidates[0].origin_match);
+    }
+}
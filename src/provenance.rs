@@ -0,0 +1,855 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use csv::ReaderBuilder;
+use xmltree::{Element, XMLNode};
+
+use crate::csv_document::sniff_delimiter;
+use crate::document::DocumentType;
+use crate::xml_document::get_qualified_name;
+
+/// HTML elements that never have a closing tag
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link",
+    "meta", "param", "source", "track", "wbr",
+];
+
+/// A span of the original document text
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SourcePosition {
+    /// Byte offset of the start of the node
+    pub start: usize,
+    /// Byte offset just past the end of the node
+    pub end: usize,
+    /// Line of `start`, starting from 1
+    pub line: usize,
+    /// Column of `start` in characters, starting from 1
+    pub column: usize,
+}
+
+/// Where a node came from in the original document
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// XPath-like path to the node, such as `/html[1]/body[1]/p[2]/text()[1]`
+    pub path: String,
+    /// Missing when the node has no counterpart in the source text, such as
+    /// elements implied by the HTML parser
+    pub position: Option<SourcePosition>,
+    /// Set for elements added by the parser without a tag in the source
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub implied: bool,
+}
+
+/// Provenance of a node and of each of its children, in the same order as
+/// the children of the XML node it was built for
+#[derive(Clone, Debug, Default)]
+pub struct ProvenanceTree {
+    pub provenance: Provenance,
+    pub children: Vec<Arc<ProvenanceTree>>,
+}
+
+impl ProvenanceTree {
+    pub fn get_child(&self, index: usize) -> Arc<ProvenanceTree> {
+        self.children.get(index).cloned().unwrap_or_default()
+    }
+}
+
+/// Byte spans of a node and its children, aligned like `ProvenanceTree`
+#[derive(Clone, Debug, Default)]
+struct SpanTree {
+    span: Option<(usize, usize)>,
+    implied: bool,
+    children: Vec<SpanTree>,
+}
+
+impl SpanTree {
+    fn leaf(span: Option<(usize, usize)>) -> Self {
+        SpanTree {
+            span,
+            implied: false,
+            children: Vec::new(),
+        }
+    }
+
+    fn branch(span: Option<(usize, usize)>, children: Vec<SpanTree>) -> Self {
+        SpanTree {
+            span,
+            implied: false,
+            children,
+        }
+    }
+}
+
+/// Works out where each node of a parsed document sits in its source text.
+/// The parsers we rely on don't report positions, and HTML is reserialized
+/// before being parsed into the element tree, so nodes are aligned with the
+/// source after the fact in a way that suits each document type.
+pub fn locate_sources(
+    element: &Element,
+    data: &str,
+    document_type: &DocumentType,
+) -> ProvenanceTree {
+    let spans = match document_type {
+        DocumentType::HTML => MarkupLocator::new(data, true).locate_element(element),
+        DocumentType::XML => MarkupLocator::new(data, false).locate_element(element),
+        DocumentType::JSON => locate_json(data),
+        DocumentType::PLAIN_TEXT => locate_plain_text(element, data),
+        DocumentType::CSV => locate_csv(element, data),
+        DocumentType::YAML | DocumentType::SQLITE => SpanTree::default(),
+    };
+
+    let line_starts = get_line_starts(data);
+    let path = format!("/{}[1]", get_qualified_name(element));
+
+    build_element_tree(element, path, spans, data, &line_starts)
+}
+
+fn build_element_tree(
+    element: &Element,
+    path: String,
+    spans: SpanTree,
+    data: &str,
+    line_starts: &[usize],
+) -> ProvenanceTree {
+    let mut step_counts: HashMap<String, usize> = HashMap::new();
+    let mut child_spans = spans.children.into_iter();
+
+    let children = element.children
+        .iter()
+        .map(|child| {
+            let step = match child {
+                XMLNode::Element(child_element) => get_qualified_name(child_element),
+                XMLNode::Text(_) | XMLNode::CData(_) => String::from("text()"),
+                XMLNode::Comment(_) => String::from("comment()"),
+                XMLNode::ProcessingInstruction(name, _) => {
+                    format!("processing-instruction('{}')", name)
+                },
+            };
+
+            let count = step_counts.entry(step.clone()).or_insert(0);
+            *count += 1;
+
+            let child_path = format!("{}/{}[{}]", path, step, count);
+            let child_span = child_spans.next().unwrap_or_default();
+
+            let tree = match child {
+                XMLNode::Element(child_element) => {
+                    build_element_tree(child_element, child_path, child_span, data, line_starts)
+                },
+                _ => ProvenanceTree {
+                    provenance: Provenance {
+                        path: child_path,
+                        position: to_position(child_span.span, data, line_starts),
+                        implied: false,
+                    },
+                    children: Vec::new(),
+                },
+            };
+
+            Arc::new(tree)
+        })
+        .collect();
+
+    ProvenanceTree {
+        provenance: Provenance {
+            path,
+            position: to_position(spans.span, data, line_starts),
+            implied: spans.implied,
+        },
+        children,
+    }
+}
+
+fn get_line_starts(data: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(data.match_indices('\n').map(|(index, _)| index + 1))
+        .collect()
+}
+
+fn to_position(
+    span: Option<(usize, usize)>,
+    data: &str,
+    line_starts: &[usize],
+) -> Option<SourcePosition> {
+    let (start, end) = span?;
+    let line = line_starts.partition_point(|line_start| *line_start <= start);
+    let line_start = line_starts[line - 1];
+
+    Some(SourcePosition {
+        start,
+        end,
+        line,
+        column: data.get(line_start..start)?.chars().count() + 1,
+    })
+}
+
+/// Walks HTML or XML source in document order, matching each element to
+/// the next opening tag and each text node to the next run of character
+/// data. An element whose name differs from the next opening tag was implied
+/// by the parser and has no position of its own.
+struct MarkupLocator<'a> {
+    data: &'a str,
+    haystack: String,
+    cursor: usize,
+    is_html: bool,
+}
+
+impl<'a> MarkupLocator<'a> {
+    fn new(data: &'a str, is_html: bool) -> Self {
+        let haystack = if is_html {
+            data.to_ascii_lowercase()
+        } else {
+            data.to_string()
+        };
+
+        MarkupLocator {
+            data,
+            haystack,
+            cursor: 0,
+            is_html,
+        }
+    }
+
+    fn locate_element(&mut self, element: &Element) -> SpanTree {
+        let mut name = get_qualified_name(element);
+        if self.is_html {
+            name = name.to_ascii_lowercase();
+        }
+
+        let start = match self.find_next_open_tag(self.cursor) {
+            Some((start, next_name)) if next_name == name => start,
+            _ => {
+                // Implied by the parser, so only its children are in the source
+                let children = self.locate_children(element);
+
+                return SpanTree {
+                    span: None,
+                    implied: true,
+                    children,
+                };
+            }
+        };
+
+        let tag_end = self.find_tag_end(start);
+        self.cursor = tag_end;
+
+        let is_empty = self.data[..tag_end].ends_with("/>")
+            || (self.is_html && VOID_ELEMENTS.contains(&name.as_str()));
+
+        let children = self.locate_children(element);
+
+        let end = if is_empty {
+            tag_end
+        } else {
+            match self.find_close_tag(&name) {
+                Some(end) => {
+                    self.cursor = end;
+                    end
+                },
+                None => self.cursor,
+            }
+        };
+
+        SpanTree::branch(Some((start, end)), children)
+    }
+
+    fn locate_children(&mut self, element: &Element) -> Vec<SpanTree> {
+        element.children
+            .iter()
+            .map(|child| match child {
+                XMLNode::Element(child_element) => self.locate_element(child_element),
+                XMLNode::Text(_) => SpanTree::leaf(self.locate_text()),
+                XMLNode::CData(_) => SpanTree::leaf(self.locate_delimited("<![CDATA[", "]]>")),
+                XMLNode::Comment(_) => SpanTree::leaf(self.locate_delimited("<!--", "-->")),
+                XMLNode::ProcessingInstruction(_, _) => SpanTree::leaf(self.locate_delimited("<?", "?>")),
+            })
+            .collect()
+    }
+
+    fn find_open_tag(&self, name: &str, from: usize) -> Option<usize> {
+        let pattern = format!("<{}", name);
+        let mut offset = from;
+
+        while let Some(index) = self.haystack.get(offset..)?.find(&pattern) {
+            let start = offset + index;
+            let next = self.haystack[start + pattern.len()..].chars().next();
+
+            if next.is_none_or(|c| c.is_whitespace() || c == '>' || c == '/') {
+                return Some(start);
+            }
+
+            offset = start + pattern.len();
+        }
+
+        None
+    }
+
+    /// Returns the offset and name of the next opening tag, skipping closing
+    /// tags, comments, CDATA sections, doctypes and processing instructions
+    fn find_next_open_tag(&self, from: usize) -> Option<(usize, String)> {
+        let mut offset = from;
+
+        loop {
+            let start = offset + self.haystack.get(offset..)?.find('<')?;
+            let rest = &self.haystack[start..];
+
+            let skip_to = if rest.starts_with("<!--") {
+                Some("-->")
+            } else if rest.starts_with("<![CDATA[") {
+                Some("]]>")
+            } else if rest.starts_with("<?") {
+                Some("?>")
+            } else if rest.starts_with("</") || rest.starts_with("<!") {
+                Some(">")
+            } else {
+                None
+            };
+
+            if let Some(marker) = skip_to {
+                offset = start + rest.find(marker)? + marker.len();
+                continue;
+            }
+
+            let name: String = rest[1..]
+                .chars()
+                .take_while(|c| !c.is_whitespace() && *c != '>' && *c != '/')
+                .collect();
+
+            if name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+                return Some((start, name));
+            }
+
+            offset = start + 1;
+        }
+    }
+
+    /// Returns the offset just past the `>` closing the tag at `start`,
+    /// skipping any `>` inside quoted attribute values
+    fn find_tag_end(&self, start: usize) -> usize {
+        let mut quote: Option<char> = None;
+
+        for (index, c) in self.data[start..].char_indices() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {},
+                (None, '"') | (None, '\'') => quote = Some(c),
+                (None, '>') => return start + index + 1,
+                _ => {}
+            }
+        }
+
+        self.data.len()
+    }
+
+    /// Finds the closing tag of the current element. A closing tag found
+    /// after another opening tag of the same name belongs to that element,
+    /// which means the current one was closed implicitly.
+    fn find_close_tag(&self, name: &str) -> Option<usize> {
+        let pattern = format!("</{}", name);
+        let close = self.cursor + self.haystack.get(self.cursor..)?.find(&pattern)?;
+
+        if let Some(next_open) = self.find_open_tag(name, self.cursor) {
+            if self.is_html && next_open < close {
+                return None;
+            }
+        }
+
+        Some(self.find_tag_end(close))
+    }
+
+    fn locate_text(&mut self) -> Option<(usize, usize)> {
+        let remaining = &self.data[self.cursor..];
+        let start = self.cursor + (remaining.len() - remaining.trim_start().len());
+
+        if self.data[start..].starts_with('<') {
+            return None;
+        }
+
+        let end = self.data[start..]
+            .char_indices()
+            .zip(self.data[start..].chars().skip(1).map(Some).chain(std::iter::once(None)))
+            .find(|((_, c), next)| {
+                *c == '<' && next.is_some_and(|n| n.is_alphabetic() || "/!?".contains(n))
+            })
+            .map(|((index, _), _)| start + index)
+            .unwrap_or(self.data.len());
+
+        let end = start + self.data[start..end].trim_end().len();
+        self.cursor = end;
+
+        Some((start, end))
+    }
+
+    fn locate_delimited(&mut self, opening: &str, closing: &str) -> Option<(usize, usize)> {
+        let start = self.cursor + self.data.get(self.cursor..)?.find(opening)?;
+        let end = start + self.data[start..].find(closing)? + closing.len();
+
+        self.cursor = end;
+
+        Some((start, end))
+    }
+}
+
+/// Maps lines and tokens back to the text they were cut from, using the
+/// line numbers and columns recorded by `text_to_element`
+fn locate_plain_text(element: &Element, data: &str) -> SpanTree {
+    let lines: Vec<(usize, usize)> = {
+        let mut offset = 0;
+
+        data.split_inclusive('\n')
+            .map(|line| {
+                let start = offset;
+                offset += line.len();
+                (start, start + line.trim_end_matches(['\n', '\r']).len())
+            })
+            .collect()
+    };
+
+    let get_line = |element: &Element, attribute: &str| -> Option<(usize, usize)> {
+        let number: usize = element.attributes.get(attribute)?.parse().ok()?;
+        lines.get(number.checked_sub(1)?).cloned()
+    };
+
+    let blocks = element.children
+        .iter()
+        .map(|block| {
+            let block = match block {
+                XMLNode::Element(block) => block,
+                _ => return SpanTree::default(),
+            };
+
+            let line_spans: Vec<SpanTree> = block.children
+                .iter()
+                .map(|line| {
+                    let line = match line {
+                        XMLNode::Element(line) => line,
+                        _ => return SpanTree::default(),
+                    };
+                    let line_span = get_line(line, "number");
+
+                    let tokens = line.children
+                        .iter()
+                        .map(|token| {
+                            let span = match (token, line_span) {
+                                (XMLNode::Element(token), Some((line_start, line_end))) => {
+                                    locate_token(token, &data[line_start..line_end])
+                                        .map(|(start, end)| (line_start + start, line_start + end))
+                                },
+                                _ => None,
+                            };
+
+                            SpanTree::branch(span, vec![SpanTree::leaf(span)])
+                        })
+                        .collect();
+
+                    SpanTree::branch(line_span, tokens)
+                })
+                .collect();
+
+            let span = match (line_spans.first(), line_spans.last()) {
+                (Some(first), Some(last)) => first.span.zip(last.span)
+                    .map(|((start, _), (_, end))| (start, end)),
+                _ => None,
+            };
+
+            SpanTree::branch(span, line_spans)
+        })
+        .collect();
+
+    SpanTree::branch(Some((0, data.len())), blocks)
+}
+
+fn locate_token(token: &Element, line: &str) -> Option<(usize, usize)> {
+    let column: usize = token.attributes.get("column")?.parse().ok()?;
+    let start = line.char_indices().nth(column)?.0;
+    let length = line[start..].find(char::is_whitespace).unwrap_or(line.len() - start);
+
+    Some((start, start + length))
+}
+
+/// Matches rows to the records they were read from
+fn locate_csv(element: &Element, data: &str) -> SpanTree {
+    let delimiter = sniff_delimiter(data).unwrap_or(b',');
+
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(data.as_bytes());
+
+    let starts: Vec<usize> = reader.records()
+        .filter_map(|record| record.ok())
+        .map(|record| {
+            record.position().map_or(0, |position| position.byte() as usize)
+        })
+        .collect();
+
+    let rows = (0..element.children.len())
+        .map(|index| {
+            let span = starts.get(index).map(|start| {
+                let next = starts.get(index + 1).cloned().unwrap_or(data.len());
+                (*start, start + data[*start..next].trim_end().len())
+            });
+
+            SpanTree::leaf(span)
+        })
+        .collect();
+
+    SpanTree::branch(Some((0, data.len())), rows)
+}
+
+#[derive(Debug)]
+enum JsonSpanKind {
+    Object(Vec<(String, JsonSpan)>),
+    Array(Vec<JsonSpan>),
+    Scalar,
+}
+
+#[derive(Debug)]
+struct JsonSpan {
+    start: usize,
+    end: usize,
+    kind: JsonSpanKind,
+}
+
+impl JsonSpan {
+    fn get_member(&self, key: &str) -> Option<&JsonSpan> {
+        match &self.kind {
+            // Duplicate keys resolve to the last value, as they do when parsing
+            JsonSpanKind::Object(members) => members.iter().rev()
+                .find(|(member_key, _)| member_key == key)
+                .map(|(_, span)| span),
+            _ => None,
+        }
+    }
+
+    fn get_items(&self) -> &[JsonSpan] {
+        match &self.kind {
+            JsonSpanKind::Array(items) => items,
+            _ => &[],
+        }
+    }
+}
+
+/// Scans JSON for the spans of its values, walking the parsed value
+/// alongside so elements line up with `json_to_element`
+fn locate_json(data: &str) -> SpanTree {
+    let value: Value = match serde_json::from_str(data) {
+        Ok(value) => value,
+        Err(_) => return SpanTree::default(),
+    };
+
+    let mut scanner = JsonScanner { data, position: 0 };
+    let span = match scanner.scan_value() {
+        Some(span) => span,
+        None => return SpanTree::default(),
+    };
+
+    match &value {
+        Value::Array(items) => SpanTree::branch(
+            Some((span.start, span.end)),
+            items.iter()
+                .zip(span.get_items())
+                .flat_map(|(item, item_span)| locate_json_value(item, item_span))
+                .collect(),
+        ),
+        _ => locate_json_value(&value, &span).pop().unwrap_or_default(),
+    }
+}
+
+fn locate_json_value(value: &Value, span: &JsonSpan) -> Vec<SpanTree> {
+    let own_span = Some((span.start, span.end));
+
+    match value {
+        Value::Object(map) => {
+            let children = map.iter()
+                .filter(|(_, child)| matches!(child, Value::Object(_) | Value::Array(_)))
+                .flat_map(|(key, child)| {
+                    match span.get_member(key) {
+                        Some(child_span) => locate_json_value(child, child_span),
+                        None => Vec::new(),
+                    }
+                })
+                .collect();
+
+            vec![SpanTree::branch(own_span, children)]
+        },
+        Value::Array(items) => {
+            items.iter()
+                .zip(span.get_items())
+                .flat_map(|(item, item_span)| locate_json_value(item, item_span))
+                .collect()
+        },
+        _ => {
+            let has_text = !matches!(value, Value::Null) && value.as_str() != Some("");
+
+            let children = if has_text { vec![SpanTree::leaf(own_span)] } else { Vec::new() };
+
+            vec![SpanTree::branch(own_span, children)]
+        }
+    }
+}
+
+struct JsonScanner<'a> {
+    data: &'a str,
+    position: usize,
+}
+
+impl<'a> JsonScanner<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.data.as_bytes().get(self.position).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn scan_value(&mut self) -> Option<JsonSpan> {
+        self.skip_whitespace();
+        let start = self.position;
+
+        let kind = match self.peek()? {
+            b'{' => {
+                self.position += 1;
+                let mut members = Vec::new();
+
+                loop {
+                    self.skip_whitespace();
+                    if self.peek()? == b'}' {
+                        self.position += 1;
+                        break;
+                    }
+
+                    let key_start = self.position;
+                    self.scan_string()?;
+                    let key: String = serde_json::from_str(&self.data[key_start..self.position]).ok()?;
+
+                    self.skip_whitespace();
+                    if self.peek()? != b':' {
+                        return None;
+                    }
+                    self.position += 1;
+
+                    members.push((key, self.scan_value()?));
+
+                    self.skip_whitespace();
+                    match self.peek()? {
+                        b',' => self.position += 1,
+                        b'}' => {
+                            self.position += 1;
+                            break;
+                        },
+                        _ => return None,
+                    }
+                }
+
+                JsonSpanKind::Object(members)
+            },
+            b'[' => {
+                self.position += 1;
+                let mut items = Vec::new();
+
+                loop {
+                    self.skip_whitespace();
+                    if self.peek()? == b']' {
+                        self.position += 1;
+                        break;
+                    }
+
+                    items.push(self.scan_value()?);
+
+                    self.skip_whitespace();
+                    match self.peek()? {
+                        b',' => self.position += 1,
+                        b']' => {
+                            self.position += 1;
+                            break;
+                        },
+                        _ => return None,
+                    }
+                }
+
+                JsonSpanKind::Array(items)
+            },
+            b'"' => {
+                self.scan_string()?;
+                JsonSpanKind::Scalar
+            },
+            _ => {
+                while self.peek().is_some_and(|byte| {
+                    !byte.is_ascii_whitespace() && !b",]}".contains(&byte)
+                }) {
+                    self.position += 1;
+                }
+                JsonSpanKind::Scalar
+            }
+        };
+
+        Some(JsonSpan {
+            start,
+            end: self.position,
+            kind,
+        })
+    }
+
+    fn scan_string(&mut self) -> Option<()> {
+        if self.peek()? != b'"' {
+            return None;
+        }
+        self.position += 1;
+
+        loop {
+            match self.peek()? {
+                b'\\' => self.position += 2,
+                b'"' => {
+                    self.position += 1;
+                    return Some(());
+                },
+                _ => self.position += 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xml_document::parse_xml;
+
+    fn get_node<'a>(tree: &'a ProvenanceTree, indices: &[usize]) -> &'a Provenance {
+        match indices.split_first() {
+            Some((index, rest)) => get_node(&tree.children[*index], rest),
+            None => &tree.provenance,
+        }
+    }
+
+    fn get_span(tree: &ProvenanceTree, indices: &[usize]) -> Option<(usize, usize)> {
+        get_node(tree, indices).position.as_ref().map(|position| (position.start, position.end))
+    }
+
+    /// Locates `source` against the tree the HTML parser builds for it,
+    /// given here as XHTML
+    fn locate_html(parsed: &str, source: &str) -> ProvenanceTree {
+        locate_sources(&parse_xml(parsed).unwrap(), source, &DocumentType::HTML)
+    }
+
+    #[test]
+    fn implied_elements_have_no_position() {
+        let source = "<p>hi</p>";
+        let tree = locate_html("<html><head></head><body><p>hi</p></body></html>", source);
+
+        for indices in [&[][..], &[0], &[1]] {
+            assert!(get_node(&tree, indices).implied);
+            assert_eq!(get_span(&tree, indices), None);
+        }
+
+        assert!(!get_node(&tree, &[1, 0]).implied);
+        assert_eq!(get_span(&tree, &[1, 0]), Some((0, source.len())));
+        assert_eq!(get_span(&tree, &[1, 0, 0]), Some((3, 5)));
+        assert_eq!(get_node(&tree, &[1, 0, 0]).path, "/html[1]/body[1]/p[1]/text()[1]");
+    }
+
+    #[test]
+    fn implied_tbody_is_not_matched_to_a_later_table() {
+        let source = "<table><tr><td>one</td></tr></table><table><tbody><tr><td>two</td></tr></tbody></table>";
+        let tree = locate_html(
+            "<html><head></head><body>\
+                <table><tbody><tr><td>one</td></tr></tbody></table>\
+                <table><tbody><tr><td>two</td></tr></tbody></table>\
+            </body></html>",
+            source,
+        );
+
+        let second_table = source.rfind("<table>").unwrap();
+        let second_tbody = source.find("<tbody>").unwrap();
+
+        assert_eq!(get_span(&tree, &[1, 0]), Some((0, second_table)));
+        assert!(get_node(&tree, &[1, 0, 0]).implied);
+        assert_eq!(get_span(&tree, &[1, 0, 0, 0]), Some((7, source.find("</table>").unwrap())));
+        assert_eq!(get_span(&tree, &[1, 0, 0, 0, 0, 0]), Some((15, 18)));
+
+        assert_eq!(get_span(&tree, &[1, 1]), Some((second_table, source.len())));
+        assert!(!get_node(&tree, &[1, 1, 0]).implied);
+        assert_eq!(get_span(&tree, &[1, 1, 0]).map(|(start, _)| start), Some(second_tbody));
+    }
+
+    #[test]
+    fn locates_unclosed_html_elements() {
+        let source = "<ul><li>a<li>b</ul>";
+        let tree = locate_html("<html><head></head><body><ul><li>a</li><li>b</li></ul></body></html>", source);
+
+        assert_eq!(get_span(&tree, &[1, 0]), Some((0, source.len())));
+        assert_eq!(get_span(&tree, &[1, 0, 0, 0]), Some((8, 9)));
+        assert_eq!(get_span(&tree, &[1, 0, 1]).map(|(start, _)| start), Some(9));
+        assert_eq!(get_span(&tree, &[1, 0, 1, 0]), Some((13, 14)));
+    }
+
+    #[test]
+    fn locates_xml_nodes() {
+        let source = "<?xml version=\"1.0\"?>\n<feed><entry id=\"1\"><title>a &amp; b</title></entry>\n<!-- c --><entry/></feed>";
+        let tree = locate_sources(&parse_xml(source).unwrap(), source, &DocumentType::XML);
+
+        let title = source.find("<title>").unwrap();
+        let comment = source.find("<!--").unwrap();
+        let empty_entry = source.find("<entry/>").unwrap();
+
+        let root = get_node(&tree, &[]).position.clone().unwrap();
+        assert_eq!((root.line, root.column), (2, 1));
+
+        assert_eq!(get_span(&tree, &[0, 0]).map(|(start, _)| start), Some(title));
+        assert_eq!(get_span(&tree, &[0, 0, 0]), Some((title + 7, title + 16)));
+        assert_eq!(get_span(&tree, &[1]), Some((comment, comment + 10)));
+        assert_eq!(get_span(&tree, &[2]), Some((empty_entry, empty_entry + 8)));
+        assert_eq!(get_node(&tree, &[2]).path, "/feed[1]/entry[2]");
+    }
+
+    #[test]
+    fn scans_json_spans() {
+        let data = r#"{"a": [1, {"b": "x"}], "c": true}"#;
+        let mut scanner = JsonScanner { data, position: 0 };
+        let span = scanner.scan_value().unwrap();
+
+        assert_eq!((span.start, span.end), (0, data.len()));
+
+        let a = span.get_member("a").unwrap();
+        assert_eq!(&data[a.start..a.end], r#"[1, {"b": "x"}]"#);
+        assert_eq!(&data[a.get_items()[1].start..a.get_items()[1].end], r#"{"b": "x"}"#);
+
+        let c = span.get_member("c").unwrap();
+        assert_eq!(&data[c.start..c.end], "true");
+    }
+
+    #[test]
+    fn scans_escaped_json_strings() {
+        let data = r#"{"k\"ey": "va\\\"l}ue", "next": "é\\"}"#;
+        let mut scanner = JsonScanner { data, position: 0 };
+        let span = scanner.scan_value().unwrap();
+
+        let value = span.get_member("k\"ey").unwrap();
+        assert_eq!(&data[value.start..value.end], r#""va\\\"l}ue""#);
+
+        let next = span.get_member("next").unwrap();
+        assert_eq!(&data[next.start..next.end], r#""é\\""#);
+        assert_eq!(span.end, data.len());
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        for data in [r#"{"a" 1}"#, r#"[1 2]"#, r#"{"a": "unterminated}"#] {
+            assert!(JsonScanner { data, position: 0 }.scan_value().is_none());
+        }
+    }
+
+    #[test]
+    fn locates_json_records() {
+        let data = r#"[{"a": 1}, {"a": 2}]"#;
+        let spans = locate_json(data);
+
+        assert_eq!(spans.span, Some((0, data.len())));
+        assert_eq!(spans.children.len(), 2);
+        assert_eq!(spans.children[1].span, Some((11, 19)));
+    }
+}
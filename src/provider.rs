@@ -1,44 +1,458 @@
 use async_trait::async_trait;
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use serde_json::Value;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::{Serialize, Deserialize};
 use serde_yaml;
+use rusqlite::{Connection, params, params_from_iter, OptionalExtension};
+use url::Url;
 
 use crate::prelude::*;
-use crate::profile::Profile;
+use crate::profile::{Profile, ProfileMatch, OriginPattern, get_similarity_threshold, sort_profile_matches};
+use crate::profile_matcher::{ProfileMatcher, get_signature, get_band_keys};
 use crate::basis_node::BasisNode;
+use crate::basis_network::BasisNetwork;
+use crate::basis_graph::BasisGraph;
+use crate::config::ProviderType;
 
 #[async_trait]
-pub trait Provider: Send + Sync + Sized + 'static {
+pub trait Provider: Send + Sync + 'static {
     async fn get_profile(
         &self,
-        features: &HashSet<Hash>
-    ) -> Result<Option<Profile>, Errors>;
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
+    ) -> Result<Option<Profile>, Errors> {
+        let candidates = self.get_profile_candidates(features, origin).await?;
+
+        Ok(candidates.into_iter().next().map(|candidate| candidate.profile))
+    }
+    async fn get_profile_candidates(
+        &self,
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
+    ) -> Result<Vec<ProfileMatch>, Errors>;
+    async fn get_profiles(&self) -> Result<Vec<Profile>, Errors>;
     async fn get_basis_node_by_lineage(
         &self,
         lineage: &Lineage
     ) -> Result<Option<BasisNode>, Errors>;
+    async fn save_profile(
+        &self,
+        profile: &Profile
+    ) -> Result<(), Errors>;
+    async fn save_basis_node(
+        &self,
+        basis_node: &BasisNode
+    ) -> Result<(), Errors>;
+    async fn save_basis_network(
+        &self,
+        basis_network: &BasisNetwork
+    ) -> Result<(), Errors>;
+    async fn save_basis_graph(
+        &self,
+        basis_graph: &BasisGraph
+    ) -> Result<(), Errors>;
+}
+
+pub fn get_provider(provider_type: &ProviderType, path: &str) -> Arc<dyn Provider> {
+    match provider_type {
+        ProviderType::Yaml => Arc::new(YamlFileProvider::new(path.to_string())),
+        ProviderType::Json => Arc::new(JsonFileProvider::new(path.to_string())),
+        ProviderType::Sqlite => Arc::new(SqliteProvider::new(path.to_string())),
+        ProviderType::Void => Arc::new(VoidProvider),
+    }
+}
+
+/// Everything a provider knows about, in the layout used by the file providers
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ProviderData {
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub basis_nodes: HashMap<String, BasisNode>,
+    #[serde(default)]
+    pub basis_networks: Vec<BasisNetwork>,
+    #[serde(default)]
+    pub basis_graphs: Vec<BasisGraph>,
+}
+
+impl ProviderData {
+    fn upsert_profile(&mut self, profile: &Profile) {
+        if let Some(existing) = self.profiles.iter_mut().find(|p| p.id == profile.id) {
+            *existing = profile.clone();
+        } else {
+            self.profiles.push(profile.clone());
+        }
+    }
+
+    fn upsert_basis_node(&mut self, basis_node: &BasisNode) {
+        self.basis_nodes.insert(basis_node.lineage.to_string(), basis_node.clone());
+    }
+
+    fn upsert_basis_network(&mut self, basis_network: &BasisNetwork) {
+        if let Some(existing) = self.basis_networks.iter_mut().find(|n| n.id == basis_network.id) {
+            *existing = basis_network.clone();
+        } else {
+            self.basis_networks.push(basis_network.clone());
+        }
+    }
+
+    fn upsert_basis_graph(&mut self, basis_graph: &BasisGraph) {
+        if let Some(existing) = self.basis_graphs.iter_mut().find(|g| g.id == basis_graph.id) {
+            *existing = basis_graph.clone();
+        } else {
+            self.basis_graphs.push(basis_graph.clone());
+        }
+    }
 }
 
 pub struct VoidProvider;
 
 #[async_trait]
 impl Provider for VoidProvider {
+    async fn get_profile_candidates(
+        &self,
+        _features: &HashSet<Hash>,
+        _origin: &Option<String>,
+    ) -> Result<Vec<ProfileMatch>, Errors> {
+        Ok(Vec::new())
+    }
+
+    async fn get_profiles(&self) -> Result<Vec<Profile>, Errors> {
+        Ok(Vec::new())
+    }
+
+    async fn get_basis_node_by_lineage(
+        &self,
+        lineage: &Lineage
+    ) -> Result<Option<BasisNode>, Errors> {
+        Ok(None)
+    }
+
+    async fn save_profile(
+        &self,
+        _profile: &Profile
+    ) -> Result<(), Errors> {
+        Ok(())
+    }
+
+    async fn save_basis_node(
+        &self,
+        _basis_node: &BasisNode
+    ) -> Result<(), Errors> {
+        Ok(())
+    }
+
+    async fn save_basis_network(
+        &self,
+        _basis_network: &BasisNetwork
+    ) -> Result<(), Errors> {
+        Ok(())
+    }
+
+    async fn save_basis_graph(
+        &self,
+        _basis_graph: &BasisGraph
+    ) -> Result<(), Errors> {
+        Ok(())
+    }
+}
+
+pub struct MemoryProvider {
+    data: RwLock<ProviderData>,
+    matcher: RwLock<ProfileMatcher>,
+}
+
+impl Default for MemoryProvider {
+    fn default() -> Self {
+        MemoryProvider::new()
+    }
+}
+
+impl MemoryProvider {
+    pub fn new() -> Self {
+        MemoryProvider::from_data(ProviderData::default())
+    }
+
+    pub fn from_data(data: ProviderData) -> Self {
+        let matcher = ProfileMatcher::from_profiles(&data.profiles, get_similarity_threshold());
+
+        MemoryProvider {
+            data: RwLock::new(data),
+            matcher: RwLock::new(matcher),
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for MemoryProvider {
+    async fn get_profile_candidates(
+        &self,
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
+    ) -> Result<Vec<ProfileMatch>, Errors> {
+        Ok(read_lock!(self.matcher).get_candidates(features, origin))
+    }
+
+    async fn get_profiles(&self) -> Result<Vec<Profile>, Errors> {
+        Ok(read_lock!(self.data).profiles.clone())
+    }
+
+    async fn get_basis_node_by_lineage(
+        &self,
+        lineage: &Lineage
+    ) -> Result<Option<BasisNode>, Errors> {
+        let data = read_lock!(self.data);
+
+        Ok(data.basis_nodes.get(&lineage.to_string()).cloned())
+    }
+
+    async fn save_profile(
+        &self,
+        profile: &Profile
+    ) -> Result<(), Errors> {
+        write_lock!(self.data).upsert_profile(profile);
+        write_lock!(self.matcher).insert(profile.clone());
+
+        Ok(())
+    }
+
+    async fn save_basis_node(
+        &self,
+        basis_node: &BasisNode
+    ) -> Result<(), Errors> {
+        write_lock!(self.data).upsert_basis_node(basis_node);
+
+        Ok(())
+    }
+
+    async fn save_basis_network(
+        &self,
+        basis_network: &BasisNetwork
+    ) -> Result<(), Errors> {
+        write_lock!(self.data).upsert_basis_network(basis_network);
+
+        Ok(())
+    }
+
+    async fn save_basis_graph(
+        &self,
+        basis_graph: &BasisGraph
+    ) -> Result<(), Errors> {
+        write_lock!(self.data).upsert_basis_graph(basis_graph);
+
+        Ok(())
+    }
+}
+
+/// Queries an ordered list of providers and returns the first hit. Hits can
+/// optionally be copied into the layers that missed, and saves are written
+/// through to every layer. A save is attempted on every layer even when an
+/// earlier one fails, so a failed save can still have been written to some
+/// of the layers.
+pub struct LayeredProvider {
+    layers: Vec<Arc<dyn Provider>>,
+    promote: bool,
+}
+
+impl LayeredProvider {
+    pub fn new(layers: Vec<Arc<dyn Provider>>) -> Self {
+        LayeredProvider {
+            layers,
+            promote: false,
+        }
+    }
+
+    pub fn with_promotion(mut self, promote: bool) -> Self {
+        self.promote = promote;
+        self
+    }
+}
+
+#[async_trait]
+impl Provider for LayeredProvider {
     async fn get_profile(
         &self,
-        _features: &HashSet<Hash>
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
     ) -> Result<Option<Profile>, Errors> {
+        for (index, layer) in self.layers.iter().enumerate() {
+            match layer.get_profile(features, origin).await {
+                Ok(Some(profile)) => {
+                    log::info!("Found profile in provider layer {}", index);
+
+                    if self.promote {
+                        for faster_layer in self.layers[..index].iter() {
+                            if let Err(err) = faster_layer.save_profile(&profile).await {
+                                log::warn!("Could not promote profile: {:?}", err);
+                            }
+                        }
+                    }
+
+                    return Ok(Some(profile));
+                },
+                Ok(None) => {},
+                Err(err) => {
+                    log::warn!("Provider layer {} failed to get profile: {:?}", index, err);
+                }
+            }
+        }
+
         Ok(None)
     }
 
+    async fn get_profile_candidates(
+        &self,
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
+    ) -> Result<Vec<ProfileMatch>, Errors> {
+        let mut candidates: Vec<ProfileMatch> = Vec::new();
+
+        for (index, layer) in self.layers.iter().enumerate() {
+            match layer.get_profile_candidates(features, origin).await {
+                Ok(layer_candidates) => {
+                    for candidate in layer_candidates {
+                        if !candidates.iter().any(|c| c.profile.id == candidate.profile.id) {
+                            candidates.push(candidate);
+                        }
+                    }
+                },
+                Err(err) => {
+                    log::warn!("Provider layer {} failed to get profile candidates: {:?}", index, err);
+                }
+            }
+        }
+
+        sort_profile_matches(&mut candidates);
+
+        Ok(candidates)
+    }
+
+    async fn get_profiles(&self) -> Result<Vec<Profile>, Errors> {
+        let mut profiles: Vec<Profile> = Vec::new();
+
+        for (index, layer) in self.layers.iter().enumerate() {
+            match layer.get_profiles().await {
+                Ok(layer_profiles) => {
+                    for profile in layer_profiles {
+                        if !profiles.iter().any(|p| p.id == profile.id) {
+                            profiles.push(profile);
+                        }
+                    }
+                },
+                Err(err) => {
+                    log::warn!("Provider layer {} failed to get profiles: {:?}", index, err);
+                }
+            }
+        }
+
+        Ok(profiles)
+    }
+
     async fn get_basis_node_by_lineage(
         &self,
         lineage: &Lineage
     ) -> Result<Option<BasisNode>, Errors> {
+        for (index, layer) in self.layers.iter().enumerate() {
+            match layer.get_basis_node_by_lineage(lineage).await {
+                Ok(Some(basis_node)) => {
+                    log::info!("Found basis node in provider layer {}", index);
+
+                    if self.promote {
+                        for faster_layer in self.layers[..index].iter() {
+                            if let Err(err) = faster_layer.save_basis_node(&basis_node).await {
+                                log::warn!("Could not promote basis node: {:?}", err);
+                            }
+                        }
+                    }
+
+                    return Ok(Some(basis_node));
+                },
+                Ok(None) => {},
+                Err(err) => {
+                    log::warn!("Provider layer {} failed to get basis node: {:?}", index, err);
+                }
+            }
+        }
+
         Ok(None)
     }
+
+    async fn save_profile(
+        &self,
+        profile: &Profile
+    ) -> Result<(), Errors> {
+        let mut results = Vec::new();
+
+        for layer in self.layers.iter() {
+            results.push(layer.save_profile(profile).await);
+        }
+
+        combine_layer_results(results)
+    }
+
+    async fn save_basis_node(
+        &self,
+        basis_node: &BasisNode
+    ) -> Result<(), Errors> {
+        let mut results = Vec::new();
+
+        for layer in self.layers.iter() {
+            results.push(layer.save_basis_node(basis_node).await);
+        }
+
+        combine_layer_results(results)
+    }
+
+    async fn save_basis_network(
+        &self,
+        basis_network: &BasisNetwork
+    ) -> Result<(), Errors> {
+        let mut results = Vec::new();
+
+        for layer in self.layers.iter() {
+            results.push(layer.save_basis_network(basis_network).await);
+        }
+
+        combine_layer_results(results)
+    }
+
+    async fn save_basis_graph(
+        &self,
+        basis_graph: &BasisGraph
+    ) -> Result<(), Errors> {
+        let mut results = Vec::new();
+
+        for layer in self.layers.iter() {
+            results.push(layer.save_basis_graph(basis_graph).await);
+        }
+
+        combine_layer_results(results)
+    }
 }
 
+fn combine_layer_results(results: Vec<Result<(), Errors>>) -> Result<(), Errors> {
+    let errors: Vec<Errors> = results
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, result)| result.err().map(|err| {
+            log::warn!("Provider layer {} failed to save: {:?}", index, err);
+            err
+        }))
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Errors::ProviderLayersError(errors))
+    }
+}
+
+/// Keeps everything in a single YAML file. The file is owned by the
+/// provider: each save rewrites it in full, so comments and formatting added
+/// by hand are not kept.
 pub struct YamlFileProvider {
     file_path: String,
 }
@@ -47,56 +461,106 @@ impl YamlFileProvider {
     pub fn new(file_path: String) -> Self {
         YamlFileProvider { file_path }
     }
-}
 
-#[async_trait]
-impl Provider for YamlFileProvider {
-    async fn get_profile(
-        &self,
-        features: &HashSet<Hash>
-    ) -> Result<Option<Profile>, Errors> {
+    fn load_data(&self) -> Result<ProviderData, Errors> {
+        if !Path::new(&self.file_path).exists() {
+            return Ok(ProviderData::default());
+        }
+
         let data = fs::read_to_string(&self.file_path)
             .map_err(|_| Errors::FileReadError)?;
 
-        let serialized_features = serde_yaml::to_string(features).expect("Could not serialize to yaml");
+        let data: Option<ProviderData> = serde_yaml::from_str(&data).map_err(|err| {
+            log::error!("Failed to parse YAML: {:?}", err);
+            Errors::YamlParseError
+        })?;
 
-        log::debug!("serialized_features: {}", serialized_features);
+        Ok(data.unwrap_or_default())
+    }
 
-        let yaml_result: Result<serde_yaml::Value, _> = serde_yaml::from_str(&data);
-        if let Err(e) = yaml_result {
-            log::error!("Failed to parse YAML: {:?}", e);
-            return Err(Errors::YamlParseError);
-        }
-        let yaml = yaml_result.unwrap();
+    fn update_data<F>(&self, update: F) -> Result<(), Errors>
+    where
+        F: FnOnce(&mut ProviderData),
+    {
+        let mut data = self.load_data()?;
+        update(&mut data);
 
-        let profiles: Vec<Profile> = yaml.get("profiles")
-            .and_then(|dp| {
-                let deserialized: Result<Vec<Profile>, _> = serde_yaml::from_value(dp.clone());
-                if let Err(ref err) = deserialized {
-                    log::error!("Deserialization error: {:?}", err);
-                }
-                deserialized.ok()
-            })
-            .ok_or(Errors::YamlParseError)?;
+        let serialized = serde_yaml::to_string(&data).map_err(|err| {
+            log::error!("Failed to serialize YAML: {:?}", err);
+            Errors::YamlParseError
+        })?;
+
+        replace_file_with_text(&self.file_path, &serialized).map_err(|err| {
+            log::error!("Failed to write provider file: {:?}", err);
+            Errors::FileOutputError
+        })
+    }
+}
 
-        if let Some(target_profile) = Profile::get_similar_profile(
+#[async_trait]
+impl Provider for YamlFileProvider {
+    async fn get_profile_candidates(
+        &self,
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
+    ) -> Result<Vec<ProfileMatch>, Errors> {
+        let profiles = self.load_data()?.profiles;
+
+        Ok(Profile::get_ranked_profiles(
             &profiles,
-            features
-        ) {
-            Ok(Some(target_profile))
-        } else {
-            Ok(None)
-        }
+            features,
+            origin,
+            get_similarity_threshold()
+        ))
+    }
+
+    async fn get_profiles(&self) -> Result<Vec<Profile>, Errors> {
+        Ok(self.load_data()?.profiles)
     }
 
     async fn get_basis_node_by_lineage(
         &self,
         lineage: &Lineage
     ) -> Result<Option<BasisNode>, Errors> {
-        Ok(None)
+        let basis_node = self.load_data()?.basis_nodes.get(&lineage.to_string()).cloned();
+
+        if basis_node.is_some() {
+            log::info!("Found basis node for lineage: {}", lineage.to_string());
+        }
+
+        Ok(basis_node)
+    }
+
+    async fn save_profile(
+        &self,
+        profile: &Profile
+    ) -> Result<(), Errors> {
+        self.update_data(|data| data.upsert_profile(profile))
+    }
+
+    async fn save_basis_node(
+        &self,
+        basis_node: &BasisNode
+    ) -> Result<(), Errors> {
+        self.update_data(|data| data.upsert_basis_node(basis_node))
+    }
+
+    async fn save_basis_network(
+        &self,
+        basis_network: &BasisNetwork
+    ) -> Result<(), Errors> {
+        self.update_data(|data| data.upsert_basis_network(basis_network))
+    }
+
+    async fn save_basis_graph(
+        &self,
+        basis_graph: &BasisGraph
+    ) -> Result<(), Errors> {
+        self.update_data(|data| data.upsert_basis_graph(basis_graph))
     }
 }
 
+/// Keeps everything in a single JSON file, rewritten in full on each save
 pub struct JsonFileProvider {
     file_path: String,
 }
@@ -105,48 +569,936 @@ impl JsonFileProvider {
     pub fn new(file_path: String) -> Self {
         JsonFileProvider { file_path }
     }
-}
 
-#[async_trait]
-impl Provider for JsonFileProvider {
-    async fn get_profile(
-        &self,
-        features: &HashSet<Hash>
-    ) -> Result<Option<Profile>, Errors> {
+    fn load_data(&self) -> Result<ProviderData, Errors> {
+        if !Path::new(&self.file_path).exists() {
+            return Ok(ProviderData::default());
+        }
+
         let data = fs::read_to_string(&self.file_path)
             .map_err(|_| Errors::FileReadError)?;
 
-        let json: Value = serde_json::from_str(&data)
-            .map_err(|_| Errors::JsonParseError)?;
+        if data.trim().is_empty() {
+            return Ok(ProviderData::default());
+        }
+
+        serde_json::from_str(&data).map_err(|err| {
+            log::error!("Failed to parse JSON: {:?}", err);
+            Errors::JsonParseError
+        })
+    }
+
+    fn update_data<F>(&self, update: F) -> Result<(), Errors>
+    where
+        F: FnOnce(&mut ProviderData),
+    {
+        let mut data = self.load_data()?;
+        update(&mut data);
+
+        let serialized = serde_json::to_string_pretty(&data).map_err(|err| {
+            log::error!("Failed to serialize JSON: {:?}", err);
+            Errors::JsonParseError
+        })?;
+
+        replace_file_with_text(&self.file_path, &serialized).map_err(|err| {
+            log::error!("Failed to write provider file: {:?}", err);
+            Errors::FileOutputError
+        })
+    }
+}
 
-        let profiles: Vec<Profile> = json.get("profiles")
-            .and_then(|dp| serde_json::from_value(dp.clone()).ok())
-            .ok_or(Errors::JsonParseError)?;
+#[async_trait]
+impl Provider for JsonFileProvider {
+    async fn get_profile_candidates(
+        &self,
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
+    ) -> Result<Vec<ProfileMatch>, Errors> {
+        let profiles = self.load_data()?.profiles;
 
-        if let Some(target_profile) = Profile::get_similar_profile(
+        Ok(Profile::get_ranked_profiles(
             &profiles,
-            features
-        ) {
-            Ok(Some(target_profile))
-        } else {
-            Ok(None)
-        }
+            features,
+            origin,
+            get_similarity_threshold()
+        ))
+    }
+
+    async fn get_profiles(&self) -> Result<Vec<Profile>, Errors> {
+        Ok(self.load_data()?.profiles)
     }
 
     async fn get_basis_node_by_lineage(
         &self,
         lineage: &Lineage
     ) -> Result<Option<BasisNode>, Errors> {
-        Ok(None)
+        let basis_node = self.load_data()?.basis_nodes.get(&lineage.to_string()).cloned();
+
+        if basis_node.is_some() {
+            log::info!("Found basis node for lineage: {}", lineage.to_string());
+        }
+
+        Ok(basis_node)
+    }
+
+    async fn save_profile(
+        &self,
+        profile: &Profile
+    ) -> Result<(), Errors> {
+        self.update_data(|data| data.upsert_profile(profile))
+    }
+
+    async fn save_basis_node(
+        &self,
+        basis_node: &BasisNode
+    ) -> Result<(), Errors> {
+        self.update_data(|data| data.upsert_basis_node(basis_node))
+    }
+
+    async fn save_basis_network(
+        &self,
+        basis_network: &BasisNetwork
+    ) -> Result<(), Errors> {
+        self.update_data(|data| data.upsert_basis_network(basis_network))
+    }
+
+    async fn save_basis_graph(
+        &self,
+        basis_graph: &BasisGraph
+    ) -> Result<(), Errors> {
+        self.update_data(|data| data.upsert_basis_graph(basis_graph))
     }
 }
 
+/// Stays below the smallest limit on bound parameters of sqlite builds
+const SQLITE_MAX_PARAMETERS: usize = 999;
+
 pub struct SqliteProvider {
     db_path: String,
+    /// Whether the schema has been created and brought up to date, which
+    /// only needs doing once per provider
+    is_migrated: AtomicBool,
 }
 
 impl SqliteProvider {
     pub fn new(db_path: String) -> Self {
-        SqliteProvider { db_path }
+        SqliteProvider {
+            db_path,
+            is_migrated: AtomicBool::new(false),
+        }
+    }
+
+    fn connect(&self) -> Result<Connection, Errors> {
+        let connection = Connection::open(&self.db_path).map_err(|err| {
+            log::error!("Failed to open sqlite database: {:?}", err);
+            Errors::SqliteDatabaseConnectionError
+        })?;
+
+        // Migrating is idempotent, so racing first calls are harmless
+        if !self.is_migrated.load(Ordering::Acquire) {
+            SqliteProvider::migrate(&connection)?;
+            self.is_migrated.store(true, Ordering::Release);
+        }
+
+        Ok(connection)
+    }
+
+    fn migrate(connection: &Connection) -> Result<(), Errors> {
+        log::info!("Bringing sqlite provider tables up to date");
+
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS profiles (
+                id TEXT PRIMARY KEY,
+                description TEXT NOT NULL,
+                xml_element_transformation TEXT,
+                hash_transformation TEXT,
+                meaningful_fields TEXT,
+                origin_patterns TEXT,
+                feature_paths TEXT,
+                retain_comments INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE IF NOT EXISTS profile_features (
+                profile_id TEXT NOT NULL REFERENCES profiles(id) ON DELETE CASCADE,
+                feature TEXT NOT NULL,
+                PRIMARY KEY (profile_id, feature)
+            );
+            CREATE TABLE IF NOT EXISTS profile_buckets (
+                profile_id TEXT NOT NULL REFERENCES profiles(id) ON DELETE CASCADE,
+                band INTEGER NOT NULL,
+                bucket INTEGER NOT NULL,
+                PRIMARY KEY (band, bucket, profile_id)
+            );
+            CREATE TABLE IF NOT EXISTS profile_origin_hosts (
+                profile_id TEXT NOT NULL REFERENCES profiles(id) ON DELETE CASCADE,
+                host TEXT
+            );
+            CREATE INDEX IF NOT EXISTS profile_origin_hosts_host ON profile_origin_hosts (host);
+            CREATE INDEX IF NOT EXISTS profile_origin_hosts_profile_id ON profile_origin_hosts (profile_id);
+            CREATE TABLE IF NOT EXISTS basis_nodes (
+                lineage TEXT PRIMARY KEY,
+                id TEXT NOT NULL,
+                data TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS basis_networks (
+                id TEXT PRIMARY KEY,
+                description TEXT NOT NULL,
+                data TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS basis_graphs (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                description TEXT NOT NULL,
+                data TEXT NOT NULL
+            );"
+        ).map_err(|err| {
+            log::error!("Failed to initialize sqlite database: {:?}", err);
+            Errors::SqliteDatabaseConnectionError
+        })?;
+
+        SqliteProvider::add_missing_column(connection, "profiles", "origin_patterns", "TEXT")?;
+        SqliteProvider::add_missing_column(connection, "profiles", "feature_paths", "TEXT")?;
+
+        SqliteProvider::add_missing_column(
+            connection,
+            "profiles",
+            "retain_comments",
+            "INTEGER NOT NULL DEFAULT 0"
+        )?;
+
+        SqliteProvider::backfill_profile_buckets(connection)?;
+        SqliteProvider::backfill_profile_origin_hosts(connection)
+    }
+
+    /// Indexes profiles saved before profile_buckets existed
+    fn backfill_profile_buckets(connection: &Connection) -> Result<(), Errors> {
+        let mut statement = connection.prepare(
+            "SELECT id FROM profiles
+             WHERE id NOT IN (SELECT DISTINCT profile_id FROM profile_buckets)"
+        ).map_err(sqlite_query_error)?;
+
+        let ids: Vec<String> = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(sqlite_query_error)?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(sqlite_query_error)?;
+
+        for id in ids.iter() {
+            log::info!("Adding missing LSH buckets for profile {}", id);
+
+            let features = SqliteProvider::get_profile_features(connection, id)?;
+            SqliteProvider::insert_profile_buckets(connection, id, &features)?;
+        }
+
+        Ok(())
+    }
+
+    /// Indexes the origin patterns of profiles saved before
+    /// profile_origin_hosts existed
+    fn backfill_profile_origin_hosts(connection: &Connection) -> Result<(), Errors> {
+        let mut statement = connection.prepare(
+            "SELECT id, origin_patterns FROM profiles
+             WHERE origin_patterns IS NOT NULL
+             AND id NOT IN (SELECT DISTINCT profile_id FROM profile_origin_hosts)"
+        ).map_err(sqlite_query_error)?;
+
+        let rows: Vec<(String, Option<String>)> = statement
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)))
+            .map_err(sqlite_query_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(sqlite_query_error)?;
+
+        for (id, origin_patterns) in rows.into_iter() {
+            log::info!("Indexing origin patterns of profile {}", id);
+
+            let origin_patterns: Option<Vec<OriginPattern>> = from_json_column(origin_patterns)?;
+            SqliteProvider::insert_profile_origin_hosts(connection, &id, &origin_patterns)?;
+        }
+
+        Ok(())
+    }
+
+    /// Stores a row per origin pattern, holding the host it is limited to,
+    /// or null when the pattern has to be checked against every origin
+    fn insert_profile_origin_hosts(
+        connection: &Connection,
+        id: &str,
+        origin_patterns: &Option<Vec<OriginPattern>>,
+    ) -> Result<(), Errors> {
+        for origin_pattern in origin_patterns.iter().flatten() {
+            connection.execute(
+                "INSERT INTO profile_origin_hosts (profile_id, host) VALUES (?1, ?2)",
+                params![id, origin_pattern.get_literal_host()],
+            ).map_err(sqlite_query_error)?;
+        }
+
+        Ok(())
+    }
+
+    fn insert_profile_buckets(
+        connection: &Connection,
+        id: &str,
+        features: &HashSet<Hash>,
+    ) -> Result<(), Errors> {
+        let band_keys = get_band_keys(&get_signature(features));
+
+        for (band, key) in band_keys.into_iter().enumerate() {
+            connection.execute(
+                "INSERT OR IGNORE INTO profile_buckets (profile_id, band, bucket) VALUES (?1, ?2, ?3)",
+                params![id, band as i64, key as i64],
+            ).map_err(sqlite_query_error)?;
+        }
+
+        Ok(())
+    }
+
+    fn get_profile_features(connection: &Connection, id: &str) -> Result<HashSet<Hash>, Errors> {
+        let mut statement = connection.prepare(
+            "SELECT feature FROM profile_features WHERE profile_id = ?1"
+        ).map_err(sqlite_query_error)?;
+
+        let features = statement
+            .query_map(params![id], |row| row.get::<_, String>(0))
+            .map_err(sqlite_query_error)?
+            .map(|feature| feature.map(|value| Hash::from_value(&value)))
+            .collect::<Result<HashSet<Hash>, _>>()
+            .map_err(sqlite_query_error)?;
+
+        Ok(features)
+    }
+
+    /// Brings tables created by earlier versions up to date
+    fn add_missing_column(
+        connection: &Connection,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), Errors> {
+        let mut statement = connection.prepare(
+            &format!("SELECT name FROM pragma_table_info('{}')", table)
+        ).map_err(sqlite_query_error)?;
+
+        let columns: Vec<String> = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(sqlite_query_error)?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(sqlite_query_error)?;
+
+        if !columns.iter().any(|name| name == column) {
+            log::info!("Adding column {} to sqlite table {}", column, table);
+
+            connection.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+                [],
+            ).map_err(sqlite_query_error)?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads profiles with one query for the profiles and one for their
+    /// features per chunk of ids, skipping ids that are not found
+    fn get_profiles_by_ids(connection: &Connection, ids: &[String]) -> Result<Vec<Profile>, Errors> {
+        let mut profiles = Vec::new();
+
+        for chunk in ids.chunks(SQLITE_MAX_PARAMETERS) {
+            profiles.extend(SqliteProvider::get_profile_chunk(connection, chunk)?);
+        }
+
+        Ok(profiles)
+    }
+
+    fn get_profile_chunk(connection: &Connection, ids: &[String]) -> Result<Vec<Profile>, Errors> {
+        let placeholders: Vec<String> = (1..=ids.len()).map(|index| format!("?{}", index)).collect();
+        let placeholders = placeholders.join(", ");
+
+        let mut features: HashMap<String, HashSet<Hash>> = HashMap::new();
+
+        let mut feature_statement = connection.prepare(&format!(
+            "SELECT profile_id, feature FROM profile_features WHERE profile_id IN ({})",
+            placeholders
+        )).map_err(sqlite_query_error)?;
+
+        let feature_rows = feature_statement
+            .query_map(params_from_iter(ids.iter()), |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(sqlite_query_error)?;
+
+        for feature_row in feature_rows {
+            let (profile_id, feature) = feature_row.map_err(sqlite_query_error)?;
+            features.entry(profile_id).or_default().insert(Hash::from_value(&feature));
+        }
+
+        let mut profile_statement = connection.prepare(&format!(
+            "SELECT id, description, xml_element_transformation, hash_transformation, meaningful_fields, origin_patterns, feature_paths, retain_comments
+             FROM profiles WHERE id IN ({})",
+            placeholders
+        )).map_err(sqlite_query_error)?;
+
+        let rows = profile_statement
+            .query_map(params_from_iter(ids.iter()), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, bool>(7)?,
+                ))
+            })
+            .map_err(sqlite_query_error)?;
+
+        let mut profiles = Vec::new();
+
+        for row in rows {
+            let (
+                id,
+                description,
+                xml_element_transformation,
+                hash_transformation,
+                meaningful_fields,
+                origin_patterns,
+                feature_paths,
+                retain_comments
+            ) = row.map_err(sqlite_query_error)?;
+
+            profiles.push(Profile {
+                features: features.remove(&id).unwrap_or_default(),
+                id: ID::from_str(&id),
+                description,
+                xml_element_transformation: from_json_column(xml_element_transformation)?,
+                hash_transformation: from_json_column(hash_transformation)?,
+                meaningful_fields: from_json_column(meaningful_fields)?,
+                origin_patterns: from_json_column(origin_patterns)?,
+                feature_paths: from_json_column(feature_paths)?,
+                retain_comments,
+            });
+        }
+
+        Ok(profiles)
+    }
+
+    /// Ids of profiles with an origin pattern matching the origin. Only
+    /// profiles indexed under the origin's host, or with a pattern that isn't
+    /// limited to one host, are checked.
+    fn get_profile_ids_by_origin(connection: &Connection, origin: &str) -> Result<Vec<String>, Errors> {
+        let host = Url::parse(origin)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()));
+
+        let mut statement = connection.prepare(
+            "SELECT id, origin_patterns FROM profiles
+             WHERE id IN (
+                SELECT profile_id FROM profile_origin_hosts WHERE host = ?1
+                UNION
+                SELECT profile_id FROM profile_origin_hosts WHERE host IS NULL
+             )"
+        ).map_err(sqlite_query_error)?;
+
+        let rows = statement
+            .query_map(params![host], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)))
+            .map_err(sqlite_query_error)?;
+
+        let mut ids = Vec::new();
+
+        for row in rows {
+            let (id, origin_patterns) = row.map_err(sqlite_query_error)?;
+            let origin_patterns: Option<Vec<OriginPattern>> = from_json_column(origin_patterns)?;
+
+            if origin_patterns.unwrap_or_default().iter().any(|pattern| pattern.matches(origin)) {
+                ids.push(id);
+            }
+        }
+
+        Ok(ids)
+    }
+}
+
+#[async_trait]
+impl Provider for SqliteProvider {
+    async fn get_profile_candidates(
+        &self,
+        features: &HashSet<Hash>,
+        origin: &Option<String>,
+    ) -> Result<Vec<ProfileMatch>, Errors> {
+        let connection = self.connect()?;
+
+        let mut bucket_statement = connection.prepare(
+            "SELECT DISTINCT profile_id FROM profile_buckets WHERE band = ?1 AND bucket = ?2"
+        ).map_err(sqlite_query_error)?;
+
+        let mut profile_ids: HashSet<String> = HashSet::new();
+
+        for (band, key) in get_band_keys(&get_signature(features)).into_iter().enumerate() {
+            let ids = bucket_statement
+                .query_map(params![band as i64, key as i64], |row| row.get::<_, String>(0))
+                .map_err(sqlite_query_error)?;
+
+            for id in ids {
+                profile_ids.insert(id.map_err(sqlite_query_error)?);
+            }
+        }
+
+        if let Some(origin) = origin {
+            profile_ids.extend(SqliteProvider::get_profile_ids_by_origin(&connection, origin)?);
+        }
+
+        log::debug!("LSH buckets yielded {} candidate profiles", profile_ids.len());
+
+        let profile_ids: Vec<String> = profile_ids.into_iter().collect();
+        let profiles = SqliteProvider::get_profiles_by_ids(&connection, &profile_ids)?;
+
+        Ok(Profile::get_ranked_profiles(
+            &profiles,
+            features,
+            origin,
+            get_similarity_threshold()
+        ))
+    }
+
+    async fn get_profiles(&self) -> Result<Vec<Profile>, Errors> {
+        let connection = self.connect()?;
+
+        let mut statement = connection.prepare("SELECT id FROM profiles")
+            .map_err(sqlite_query_error)?;
+
+        let ids: Vec<String> = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(sqlite_query_error)?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(sqlite_query_error)?;
+
+        SqliteProvider::get_profiles_by_ids(&connection, &ids)
+    }
+
+    async fn get_basis_node_by_lineage(
+        &self,
+        lineage: &Lineage
+    ) -> Result<Option<BasisNode>, Errors> {
+        let connection = self.connect()?;
+
+        let data: Option<String> = connection.query_row(
+            "SELECT data FROM basis_nodes WHERE lineage = ?1",
+            params![lineage.to_string()],
+            |row| row.get(0),
+        ).optional().map_err(sqlite_query_error)?;
+
+        data.map(|data| serde_json::from_str(&data).map_err(|_| Errors::JsonParseError))
+            .transpose()
+    }
+
+    async fn save_profile(
+        &self,
+        profile: &Profile
+    ) -> Result<(), Errors> {
+        let mut connection = self.connect()?;
+        let transaction = connection.transaction().map_err(sqlite_query_error)?;
+
+        transaction.execute(
+            "INSERT OR REPLACE INTO profiles
+                (id, description, xml_element_transformation, hash_transformation, meaningful_fields, origin_patterns, feature_paths, retain_comments)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                profile.id.to_string(),
+                profile.description,
+                to_json_column(&profile.xml_element_transformation)?,
+                to_json_column(&profile.hash_transformation)?,
+                to_json_column(&profile.meaningful_fields)?,
+                to_json_column(&profile.origin_patterns)?,
+                to_json_column(&profile.feature_paths)?,
+                profile.retain_comments,
+            ],
+        ).map_err(sqlite_query_error)?;
+
+        transaction.execute(
+            "DELETE FROM profile_features WHERE profile_id = ?1",
+            params![profile.id.to_string()],
+        ).map_err(sqlite_query_error)?;
+
+        for feature in profile.features.iter() {
+            transaction.execute(
+                "INSERT OR IGNORE INTO profile_features (profile_id, feature) VALUES (?1, ?2)",
+                params![profile.id.to_string(), feature.to_string()],
+            ).map_err(sqlite_query_error)?;
+        }
+
+        transaction.execute(
+            "DELETE FROM profile_buckets WHERE profile_id = ?1",
+            params![profile.id.to_string()],
+        ).map_err(sqlite_query_error)?;
+
+        SqliteProvider::insert_profile_buckets(&transaction, &profile.id.to_string(), &profile.features)?;
+
+        transaction.execute(
+            "DELETE FROM profile_origin_hosts WHERE profile_id = ?1",
+            params![profile.id.to_string()],
+        ).map_err(sqlite_query_error)?;
+
+        SqliteProvider::insert_profile_origin_hosts(
+            &transaction,
+            &profile.id.to_string(),
+            &profile.origin_patterns
+        )?;
+
+        transaction.commit().map_err(sqlite_query_error)
+    }
+
+    async fn save_basis_node(
+        &self,
+        basis_node: &BasisNode
+    ) -> Result<(), Errors> {
+        let connection = self.connect()?;
+        let data = serde_json::to_string(basis_node).map_err(|_| Errors::JsonParseError)?;
+
+        connection.execute(
+            "INSERT OR REPLACE INTO basis_nodes (lineage, id, data) VALUES (?1, ?2, ?3)",
+            params![basis_node.lineage.to_string(), basis_node.id.to_string(), data],
+        ).map_err(sqlite_query_error)?;
+
+        Ok(())
+    }
+
+    async fn save_basis_network(
+        &self,
+        basis_network: &BasisNetwork
+    ) -> Result<(), Errors> {
+        let connection = self.connect()?;
+        let data = serde_json::to_string(basis_network).map_err(|_| Errors::JsonParseError)?;
+
+        connection.execute(
+            "INSERT OR REPLACE INTO basis_networks (id, description, data) VALUES (?1, ?2, ?3)",
+            params![basis_network.id.to_string(), basis_network.description, data],
+        ).map_err(sqlite_query_error)?;
+
+        Ok(())
+    }
+
+    async fn save_basis_graph(
+        &self,
+        basis_graph: &BasisGraph
+    ) -> Result<(), Errors> {
+        let connection = self.connect()?;
+        let data = serde_json::to_string(basis_graph).map_err(|_| Errors::JsonParseError)?;
+
+        connection.execute(
+            "INSERT OR REPLACE INTO basis_graphs (id, name, description, data) VALUES (?1, ?2, ?3, ?4)",
+            params![basis_graph.id.to_string(), basis_graph.name, basis_graph.description, data],
+        ).map_err(sqlite_query_error)?;
+
+        Ok(())
+    }
+}
+
+pub(crate) fn sqlite_query_error(err: rusqlite::Error) -> Errors {
+    log::error!("Sqlite query failed: {:?}", err);
+    Errors::SqliteDatabaseQueryError
+}
+
+fn to_json_column<T: Serialize>(value: &Option<T>) -> Result<Option<String>, Errors> {
+    value.as_ref()
+        .map(|value| serde_json::to_string(value).map_err(|_| Errors::JsonParseError))
+        .transpose()
+}
+
+fn from_json_column<T: serde::de::DeserializeOwned>(
+    column: Option<String>
+) -> Result<Option<T>, Errors> {
+    column
+        .map(|value| serde_json::from_str(&value).map_err(|_| Errors::JsonParseError))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_temporary_path(extension: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("parversion-{}.{}", ID::new().to_string(), extension))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn get_profile(features: &[&str]) -> Profile {
+        Profile {
+            id: ID::new(),
+            description: String::from("test profile"),
+            features: features.iter().map(|feature| Hash::from_str(feature)).collect(),
+            xml_element_transformation: None,
+            hash_transformation: None,
+            meaningful_fields: None,
+            origin_patterns: None,
+            feature_paths: None,
+            retain_comments: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn yaml_file_without_profiles_is_empty() {
+        let path = get_temporary_path("yaml");
+        fs::write(&path, "# seed data\nbasis_nodes: {}\n").unwrap();
+
+        let provider = YamlFileProvider::new(path.clone());
+        let candidates = provider.get_profile_candidates(&HashSet::new(), &None).await.unwrap();
+
+        assert!(candidates.is_empty());
+        assert!(provider.get_profiles().await.unwrap().is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn empty_provider_files_have_no_basis_nodes() {
+        let yaml_path = get_temporary_path("yaml");
+        let json_path = get_temporary_path("json");
+        fs::write(&yaml_path, "").unwrap();
+        fs::write(&json_path, "").unwrap();
+
+        let providers: Vec<Arc<dyn Provider>> = vec![
+            Arc::new(YamlFileProvider::new(yaml_path.clone())),
+            Arc::new(JsonFileProvider::new(json_path.clone())),
+            Arc::new(JsonFileProvider::new(get_temporary_path("json"))),
+        ];
+
+        let lineage = Lineage::from_hashes(vec![Hash::from_str("html")]);
+
+        for provider in providers.iter() {
+            assert!(provider.get_basis_node_by_lineage(&lineage).await.unwrap().is_none());
+        }
+
+        fs::remove_file(&yaml_path).unwrap();
+        fs::remove_file(&json_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_providers_save_and_load_profiles() {
+        let profile = get_profile(&["a", "b", "c"]);
+
+        let yaml_path = get_temporary_path("yaml");
+        let yaml_provider = YamlFileProvider::new(yaml_path.clone());
+        yaml_provider.save_profile(&profile).await.unwrap();
+
+        let json_path = get_temporary_path("json");
+        let json_provider = JsonFileProvider::new(json_path.clone());
+        json_provider.save_profile(&profile).await.unwrap();
+
+        for profiles in [
+            yaml_provider.get_profiles().await.unwrap(),
+            json_provider.get_profiles().await.unwrap(),
+        ] {
+            assert_eq!(profiles.len(), 1);
+            assert_eq!(profiles[0].id, profile.id);
+        }
+
+        assert!(!Path::new(&format!("{}.tmp", yaml_path)).exists());
+
+        fs::remove_file(&yaml_path).unwrap();
+        fs::remove_file(&json_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn layered_provider_promotes_hits() {
+        let profile = get_profile(&["a", "b", "c"]);
+
+        let fast = Arc::new(MemoryProvider::new());
+        let slow = Arc::new(MemoryProvider::new());
+        slow.save_profile(&profile).await.unwrap();
+
+        let layered = LayeredProvider::new(vec![fast.clone(), slow.clone()])
+            .with_promotion(true);
+
+        let found = layered.get_profile(&profile.features, &None).await.unwrap();
+
+        assert_eq!(found.map(|found| found.id), Some(profile.id.clone()));
+        assert_eq!(fast.get_profiles().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn layered_provider_without_promotion_leaves_layers() {
+        let profile = get_profile(&["a", "b", "c"]);
+
+        let fast = Arc::new(MemoryProvider::new());
+        let slow = Arc::new(MemoryProvider::new());
+        slow.save_profile(&profile).await.unwrap();
+
+        let layered = LayeredProvider::new(vec![fast.clone(), slow.clone()]);
+
+        assert!(layered.get_profile(&profile.features, &None).await.unwrap().is_some());
+        assert!(fast.get_profiles().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn layered_provider_writes_through_every_layer() {
+        let profile = get_profile(&["a", "b", "c"]);
+
+        let first = Arc::new(MemoryProvider::new());
+        let second = Arc::new(MemoryProvider::new());
+
+        let layered = LayeredProvider::new(vec![first.clone(), second.clone()]);
+        layered.save_profile(&profile).await.unwrap();
+
+        assert_eq!(first.get_profiles().await.unwrap().len(), 1);
+        assert_eq!(second.get_profiles().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn layered_provider_saves_past_a_failing_layer() {
+        let profile = get_profile(&["a", "b", "c"]);
+
+        // A directory can not be read as a provider file
+        let failing = Arc::new(YamlFileProvider::new(
+            std::env::temp_dir().to_string_lossy().to_string()
+        ));
+        let memory = Arc::new(MemoryProvider::new());
+
+        let layered = LayeredProvider::new(vec![failing, memory.clone()]);
+        let result = layered.save_profile(&profile).await;
+
+        assert!(matches!(result, Err(Errors::ProviderLayersError(ref errors)) if errors.len() == 1));
+        assert_eq!(memory.get_profiles().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sqlite_provider_backfills_missing_buckets() {
+        let path = get_temporary_path("db");
+        let provider = SqliteProvider::new(path.clone());
+        let profile = get_profile(&["a", "b", "c"]);
+
+        provider.save_profile(&profile).await.unwrap();
+        provider.connect().unwrap().execute("DELETE FROM profile_buckets", []).unwrap();
+
+        // Buckets are backfilled when a provider first connects
+        let candidates = SqliteProvider::new(path.clone())
+            .get_profile_candidates(&profile.features, &None)
+            .await
+            .unwrap();
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].profile.id, profile.id);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sqlite_provider_scores_only_matching_origins() {
+        let path = get_temporary_path("db");
+        let provider = SqliteProvider::new(path.clone());
+
+        let mut matching = get_profile(&["a", "b", "c"]);
+        matching.origin_patterns = OriginPattern::from_origin("https://example.com/").map(|pattern| vec![pattern]);
+
+        let mut other = get_profile(&["a", "b", "c"]);
+        other.origin_patterns = OriginPattern::from_origin("https://example.org/").map(|pattern| vec![pattern]);
+
+        provider.save_profile(&matching).await.unwrap();
+        provider.save_profile(&other).await.unwrap();
+
+        let connection = provider.connect().unwrap();
+        let ids = SqliteProvider::get_profile_ids_by_origin(&connection, "https://example.com/news").unwrap();
+
+        assert_eq!(ids, vec![matching.id.to_string()]);
+
+        let profiles = SqliteProvider::get_profiles_by_ids(&connection, &ids).unwrap();
+
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].features, matching.features);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn origin_patterns_reduce_to_literal_hosts() {
+        let pattern = |host: &str| OriginPattern { host: Some(host.to_string()), ..OriginPattern::default() };
+
+        assert_eq!(
+            OriginPattern::from_origin("https://www.example.com/news").unwrap().get_literal_host(),
+            Some("www.example.com".to_string())
+        );
+        assert_eq!(pattern(r"^.*\.example\.com$").get_literal_host(), None);
+        assert_eq!(pattern(r"^example\d\.com$").get_literal_host(), None);
+        assert_eq!(pattern("example.com").get_literal_host(), None);
+        assert_eq!(OriginPattern::default().get_literal_host(), None);
+    }
+
+    #[tokio::test]
+    async fn sqlite_provider_checks_patterns_without_a_literal_host() {
+        let path = get_temporary_path("db");
+        let provider = SqliteProvider::new(path.clone());
+
+        let mut subdomains = get_profile(&["a", "b", "c"]);
+        subdomains.origin_patterns = Some(vec![OriginPattern {
+            host: Some(r"^.*\.example\.com$".to_string()),
+            ..OriginPattern::default()
+        }]);
+
+        let mut exact = get_profile(&["a", "b", "c"]);
+        exact.origin_patterns = OriginPattern::from_origin("https://example.org/").map(|pattern| vec![pattern]);
+
+        provider.save_profile(&subdomains).await.unwrap();
+        provider.save_profile(&exact).await.unwrap();
+
+        let connection = provider.connect().unwrap();
+
+        assert_eq!(
+            SqliteProvider::get_profile_ids_by_origin(&connection, "https://news.example.com/").unwrap(),
+            vec![subdomains.id.to_string()]
+        );
+        assert_eq!(
+            SqliteProvider::get_profile_ids_by_origin(&connection, "https://example.org/a").unwrap(),
+            vec![exact.id.to_string()]
+        );
+        assert!(SqliteProvider::get_profile_ids_by_origin(&connection, "not a url").unwrap().is_empty());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sqlite_provider_backfills_origin_hosts_once() {
+        let path = get_temporary_path("db");
+        let provider = SqliteProvider::new(path.clone());
+
+        let mut profile = get_profile(&["a", "b", "c"]);
+        profile.origin_patterns = OriginPattern::from_origin("https://example.com/").map(|pattern| vec![pattern]);
+        provider.save_profile(&profile).await.unwrap();
+
+        provider.connect().unwrap().execute("DELETE FROM profile_origin_hosts", []).unwrap();
+
+        // The provider has already migrated, so nothing is backfilled
+        let connection = provider.connect().unwrap();
+        assert!(SqliteProvider::get_profile_ids_by_origin(&connection, "https://example.com/").unwrap().is_empty());
+
+        let connection = SqliteProvider::new(path.clone()).connect().unwrap();
+        assert_eq!(
+            SqliteProvider::get_profile_ids_by_origin(&connection, "https://example.com/").unwrap(),
+            vec![profile.id.to_string()]
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sqlite_provider_upgrades_old_profile_tables() {
+        let path = get_temporary_path("db");
+
+        Connection::open(&path).unwrap().execute_batch(
+            "CREATE TABLE profiles (
+                id TEXT PRIMARY KEY,
+                description TEXT NOT NULL,
+                xml_element_transformation TEXT,
+                hash_transformation TEXT,
+                meaningful_fields TEXT
+            );"
+        ).unwrap();
+
+        let provider = SqliteProvider::new(path.clone());
+        let profile = get_profile(&["a", "b", "c"]);
+
+        provider.save_profile(&profile).await.unwrap();
+
+        assert_eq!(provider.get_profiles().await.unwrap().len(), 1);
+
+        fs::remove_file(&path).unwrap();
     }
 }
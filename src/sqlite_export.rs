@@ -0,0 +1,346 @@
+use rusqlite::{Connection, Transaction, params_from_iter};
+use rusqlite::types::Value as SqlValue;
+use std::collections::{HashMap, HashSet};
+
+use crate::prelude::*;
+use crate::data_node::DataNode;
+use crate::document_builder::{get_data_nodes_by_id, get_graph_root, is_structural_field};
+use crate::graph_node::Graph;
+use crate::hash::Hash;
+use crate::provider::sqlite_query_error;
+
+/// Columns every record table has. Fields with the same name are written
+/// to a column with an extra leading underscore.
+const RESERVED_COLUMNS: [&str; 6] = ["_id", "_parent_id", "_lineage", "_hash", "_path", "_description"];
+
+/// Number of hash characters appended to table names, to tell apart
+/// record types sharing a tag
+const TABLE_HASH_LENGTH: usize = 8;
+
+/// Writes each record type to a table of its own, with a column per field
+/// and a foreign key to the table of its parents. Nodes with the same keys
+/// share a lineage whatever their tag, so records of a lineage are split by
+/// tag, and by the table of their parents.
+/// Rows are upserted by lineage, hash and source path, so running again
+/// over the same document updates rows rather than duplicating them.
+/// Returns a short summary of what was written.
+pub fn write_nodeset_to_sqlite(nodeset: &NodeSet, database_path: &str) -> Result<String, Errors> {
+    log::trace!("In write_nodeset_to_sqlite");
+
+    let graph_root = get_graph_root(nodeset)?;
+    let data_nodes = get_data_nodes_by_id(nodeset);
+
+    let mut parent_ids: HashMap<String, String> = HashMap::new();
+    collect_parent_ids(graph_root, None, &mut parent_ids);
+
+    let mut connection = Connection::open(database_path).map_err(|err| {
+        log::error!("Failed to open sqlite database: {:?}", err);
+        Errors::SqliteDatabaseConnectionError
+    })?;
+
+    connection.execute_batch("PRAGMA foreign_keys = ON;").map_err(sqlite_query_error)?;
+
+    let transaction = connection.transaction().map_err(sqlite_query_error)?;
+
+    let mut tables: HashSet<String> = HashSet::new();
+    let mut record_tables: HashMap<String, String> = HashMap::new();
+    let mut table_columns: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut row_ids: HashMap<String, i64> = HashMap::new();
+
+    // Data nodes come parents first, so a parent's row always exists
+    for data_node in nodeset.data_nodes.iter() {
+        let lineage = data_node.lineage.to_string();
+        let parent = parent_ids
+            .get(&data_node.id.to_string())
+            .and_then(|parent_id| data_nodes.get(parent_id));
+
+        let parent_table = parent.and_then(|parent| record_tables.get(&parent.id.to_string())).cloned();
+        let table = get_table_name(data_node, &lineage, parent_table.as_deref());
+
+        if tables.insert(table.clone()) {
+            create_table(&transaction, &table, parent_table.as_ref())?;
+        }
+
+        record_tables.insert(data_node.id.to_string(), table.clone());
+
+        let columns = match table_columns.get_mut(&table) {
+            Some(columns) => columns,
+            None => {
+                let columns = get_table_columns(&transaction, &table)?;
+                table_columns.entry(table.clone()).or_insert(columns)
+            }
+        };
+
+        let mut keys: Vec<&String> = data_node.fields
+            .keys()
+            .filter(|key| !is_structural_field(key))
+            .collect();
+        keys.sort();
+
+        for key in keys.iter() {
+            let column = get_column_name(key);
+
+            if columns.insert(column.clone()) {
+                log::info!("Adding column {} to sqlite table {}", column, table);
+
+                transaction.execute(
+                    &format!("ALTER TABLE {} ADD COLUMN {} TEXT", quote(&table), quote(&column)),
+                    [],
+                ).map_err(sqlite_query_error)?;
+            }
+        }
+
+        let parent_row_id = parent.and_then(|parent| row_ids.get(&parent.id.to_string()).copied());
+        let row_id = upsert_record(&transaction, &table, data_node, &keys, parent_row_id)?;
+
+        row_ids.insert(data_node.id.to_string(), row_id);
+    }
+
+    transaction.commit().map_err(sqlite_query_error)?;
+
+    log::info!("Wrote {} records to {} sqlite tables", row_ids.len(), tables.len());
+
+    Ok(format!(
+        "Wrote {} records to {} tables in {}\n",
+        row_ids.len(),
+        tables.len(),
+        database_path
+    ))
+}
+
+fn collect_parent_ids(
+    graph_node: &Graph,
+    parent_id: Option<&str>,
+    parent_ids: &mut HashMap<String, String>,
+) {
+    let graph_node = read_lock!(graph_node);
+    let data_node_id = graph_node.data_node_id.to_string();
+
+    if let Some(parent_id) = parent_id {
+        parent_ids.insert(data_node_id.clone(), parent_id.to_string());
+    }
+
+    for child in graph_node.children.iter() {
+        collect_parent_ids(child, Some(&data_node_id), parent_ids);
+    }
+}
+
+/// Names a table after the tag of its records, or `text` and `comment` for
+/// nodes without one, followed by the start of a hash of that name, their
+/// lineage and the table of their parents. The name depends on nothing else,
+/// so every run over the same database finds the same tables.
+fn get_table_name(data_node: &DataNode, lineage: &str, parent_table: Option<&str>) -> String {
+    let name = data_node.fields.get("tag").map(|tag| tag.as_str())
+        .or_else(|| ["text", "comment"].into_iter().find(|key| data_node.fields.contains_key(*key)))
+        .unwrap_or("record");
+
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    let name = name.trim_matches('_');
+
+    let hash = Hash::from_str(&format!("{}\n{}\n{}", name, lineage, parent_table.unwrap_or("")))
+        .to_string()
+        .expect("Hash is computed from a string");
+
+    format!("{}_{}", name, &hash[..TABLE_HASH_LENGTH])
+}
+
+fn get_column_name(key: &str) -> String {
+    if RESERVED_COLUMNS.contains(&key) {
+        format!("_{}", key)
+    } else {
+        key.to_string()
+    }
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+fn create_table(
+    transaction: &Transaction,
+    table: &str,
+    parent_table: Option<&String>,
+) -> Result<(), Errors> {
+    let parent_reference = parent_table
+        .map(|parent_table| format!(" REFERENCES {}(_id) ON DELETE CASCADE", quote(parent_table)))
+        .unwrap_or_default();
+
+    transaction.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {table} (
+            _id INTEGER PRIMARY KEY,
+            _parent_id INTEGER{parent_reference},
+            _lineage TEXT NOT NULL,
+            _hash TEXT NOT NULL,
+            _path TEXT NOT NULL,
+            _description TEXT NOT NULL,
+            UNIQUE (_lineage, _hash, _path)
+        );",
+        table = quote(table),
+        parent_reference = parent_reference,
+    )).map_err(sqlite_query_error)
+}
+
+fn get_table_columns(transaction: &Transaction, table: &str) -> Result<HashSet<String>, Errors> {
+    let mut statement = transaction.prepare(
+        "SELECT name FROM pragma_table_info(?1)"
+    ).map_err(sqlite_query_error)?;
+
+    let columns = statement
+        .query_map([table], |row| row.get::<_, String>(0))
+        .map_err(sqlite_query_error)?
+        .collect::<Result<HashSet<String>, _>>()
+        .map_err(sqlite_query_error)?;
+
+    Ok(columns)
+}
+
+/// Inserts or updates the row of a record, returning its row id
+fn upsert_record(
+    transaction: &Transaction,
+    table: &str,
+    data_node: &DataNode,
+    keys: &[&String],
+    parent_row_id: Option<i64>,
+) -> Result<i64, Errors> {
+    let mut columns: Vec<String> = ["_parent_id", "_lineage", "_hash", "_path", "_description"]
+        .iter()
+        .map(|column| column.to_string())
+        .collect();
+
+    let mut values: Vec<SqlValue> = vec![
+        parent_row_id.map_or(SqlValue::Null, SqlValue::Integer),
+        SqlValue::Text(data_node.lineage.to_string()),
+        SqlValue::Text(data_node.hash.to_string().unwrap_or_default()),
+        SqlValue::Text(data_node.provenance.path.clone()),
+        SqlValue::Text(data_node.description.clone()),
+    ];
+
+    for key in keys.iter() {
+        columns.push(get_column_name(key));
+        values.push(SqlValue::Text(data_node.fields[*key].clone()));
+    }
+
+    let quoted_columns: Vec<String> = columns.iter().map(|column| quote(column)).collect();
+    let placeholders: Vec<String> = (1..=columns.len()).map(|index| format!("?{}", index)).collect();
+    let updates: Vec<String> = quoted_columns
+        .iter()
+        .filter(|column| !matches!(column.as_str(), "\"_lineage\"" | "\"_hash\"" | "\"_path\""))
+        .map(|column| format!("{column} = excluded.{column}", column = column))
+        .collect();
+
+    transaction.query_row(
+        &format!(
+            "INSERT INTO {} ({}) VALUES ({})
+            ON CONFLICT (_lineage, _hash, _path) DO UPDATE SET {}
+            RETURNING _id",
+            quote(table),
+            quoted_columns.join(", "),
+            placeholders.join(", "),
+            updates.join(", "),
+        ),
+        params_from_iter(values),
+        |row| row.get::<_, i64>(0),
+    ).map_err(sqlite_query_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use crate::provider::VoidProvider;
+    use crate::traverse::{TraversalWithContext, traverse_with_context};
+    use std::sync::Arc;
+
+    async fn get_nodeset(text: &str) -> NodeSet {
+        let mut document = Document::from_string(text.to_string(), &None).unwrap();
+        let profile = document.perform_analysis(Arc::new(VoidProvider)).await.unwrap();
+        let TraversalWithContext { nodeset, .. } = traverse_with_context(&profile, document).unwrap();
+
+        nodeset
+    }
+
+    fn get_table_counts(database_path: &str) -> Vec<(String, i64)> {
+        let connection = Connection::open(database_path).unwrap();
+        let tables: Vec<String> = connection
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+
+        tables
+            .into_iter()
+            .map(|table| {
+                let count = connection
+                    .query_row(&format!("SELECT COUNT(*) FROM {}", quote(&table)), [], |row| row.get(0))
+                    .unwrap();
+
+                (table, count)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn splits_a_lineage_with_mixed_tags() {
+        let nodeset = get_nodeset(
+            "<html><head><title>t</title></head><body><p>x</p></body></html>"
+        ).await;
+
+        // head and body, title and p, and both texts share lineages
+        let lineages: HashSet<String> = nodeset.data_nodes
+            .iter()
+            .map(|data_node| data_node.lineage.to_string())
+            .collect();
+        assert!(lineages.len() < nodeset.data_nodes.len());
+
+        let database_path = std::env::temp_dir().join(format!("{}.db", ID::new().to_string()));
+        let database_path = database_path.to_str().unwrap();
+
+        write_nodeset_to_sqlite(&nodeset, database_path).unwrap();
+        write_nodeset_to_sqlite(&nodeset, database_path).unwrap();
+
+        let table_counts = get_table_counts(database_path);
+        let _ = std::fs::remove_file(database_path);
+
+        let names: Vec<String> = table_counts
+            .iter()
+            .map(|(table, _)| table.rsplit_once('_').unwrap().0.to_string())
+            .collect();
+
+        assert_eq!(names.len(), nodeset.data_nodes.len());
+        for name in ["html", "head", "body", "title", "p"] {
+            assert!(names.contains(&name.to_string()), "no {} table in {:?}", name, table_counts);
+        }
+        assert!(table_counts.iter().all(|(_, count)| *count == 1), "{:?}", table_counts);
+    }
+
+    #[tokio::test]
+    async fn finds_the_same_tables_across_documents() {
+        let database_path = std::env::temp_dir().join(format!("{}.db", ID::new().to_string()));
+        let database_path = database_path.to_str().unwrap();
+
+        let both = get_nodeset(
+            "<html><body><div><p>a</p></div><section><p>b</p></section></body></html>"
+        ).await;
+        let section_only = get_nodeset(
+            "<html><body><section><p>b</p></section></body></html>"
+        ).await;
+
+        write_nodeset_to_sqlite(&both, database_path).unwrap();
+        let tables_before: Vec<String> = get_table_counts(database_path)
+            .into_iter()
+            .map(|(table, _)| table)
+            .collect();
+
+        let result = write_nodeset_to_sqlite(&section_only, database_path);
+        let table_counts = get_table_counts(database_path);
+        let _ = std::fs::remove_file(database_path);
+
+        result.unwrap();
+
+        let tables_after: Vec<String> = table_counts.iter().map(|(table, _)| table.clone()).collect();
+        assert_eq!(tables_after, tables_before);
+        assert!(table_counts.iter().all(|(_, count)| *count == 1), "{:?}", table_counts);
+    }
+}
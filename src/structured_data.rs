@@ -0,0 +1,383 @@
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+
+use crate::prelude::*;
+use crate::model::{Model, MODELS};
+use crate::page_metadata::get_text;
+
+/// Common schema.org properties that carry the same meaning as a model
+/// field of a different name
+const PROPERTY_ALIASES: [(&str, &[&str]); 8] = [
+    ("source", &["url", "mainEntityOfPage", "@id", "sameAs"]),
+    ("timestamp", &["datePublished", "dateCreated", "uploadDate", "dateModified"]),
+    ("content", &["articleBody", "text", "description", "abstract"]),
+    ("author", &["creator", "publisher"]),
+    ("tags", &["keywords", "about"]),
+    ("title", &["headline", "name"]),
+    ("image", &["thumbnailUrl"]),
+    ("price", &["offers.price"]),
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StructuredDataFormat {
+    JsonLd,
+    Microdata,
+    Rdfa,
+}
+
+/// A model that a structured island maps onto
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModelMatch {
+    pub model_id: ID,
+    pub model_name: String,
+    /// The island's values under the model's field names
+    pub data: Value,
+    /// Share of the model's fields the island provides
+    pub confidence: f64,
+}
+
+/// Structured data embedded in a page by its author, such as schema.org
+/// JSON-LD, microdata or RDFa. These are extracted from the raw document
+/// before any element is blacklisted, since JSON-LD lives in `script`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StructuredData {
+    pub format: StructuredDataFormat,
+    /// Declared types, such as `Product` or `https://schema.org/Article`
+    pub types: Vec<String>,
+    /// The item as a JSON-LD style object
+    pub value: Value,
+    /// Known models providing every required field from this item, best
+    /// first. Their data can stand in for, or cross-check, LLM output.
+    pub model_matches: Vec<ModelMatch>,
+}
+
+impl StructuredData {
+    fn new(format: StructuredDataFormat, value: Value) -> Self {
+        let types = match value.get("@type") {
+            Some(Value::String(item_type)) => vec![item_type.clone()],
+            Some(Value::Array(item_types)) => item_types
+                .iter()
+                .filter_map(|item_type| item_type.as_str().map(String::from))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let mut model_matches: Vec<ModelMatch> = MODELS
+            .iter()
+            .filter_map(|model| match_model(model, &value))
+            .collect();
+
+        model_matches.sort_by(|a, b| {
+            b.confidence.partial_cmp(&a.confidence).unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        StructuredData {
+            format,
+            types,
+            value,
+            model_matches,
+        }
+    }
+}
+
+/// Extracts every JSON-LD, microdata and RDFa item from an HTML document
+pub fn extract_structured_data(dom: &RcDom) -> Vec<StructuredData> {
+    let mut items: Vec<StructuredData> = Vec::new();
+
+    collect_json_ld(&dom.document, &mut items);
+
+    for format in [StructuredDataFormat::Microdata, StructuredDataFormat::Rdfa] {
+        let mut values: Vec<Value> = Vec::new();
+        collect_items(&dom.document, &format, &mut values);
+
+        items.extend(
+            values.into_iter().map(|value| StructuredData::new(format.clone(), value))
+        );
+    }
+
+    log::debug!("Found {} structured data items", items.len());
+
+    items
+}
+
+fn collect_json_ld(node: &Handle, items: &mut Vec<StructuredData>) {
+    if let NodeData::Element { ref name, ref attrs, .. } = node.data {
+        let is_json_ld = name.local.as_ref() == "script" && attrs.borrow().iter().any(|attr| {
+            attr.name.local.as_ref() == "type"
+                && attr.value.trim().eq_ignore_ascii_case("application/ld+json")
+        });
+
+        if is_json_ld {
+            let text = get_raw_text(node);
+
+            match serde_json::from_str::<Value>(&text) {
+                Ok(value) => {
+                    for item in flatten_json_ld(value) {
+                        items.push(StructuredData::new(StructuredDataFormat::JsonLd, item));
+                    }
+                },
+                Err(err) => log::warn!("Could not parse JSON-LD: {}", err),
+            }
+
+            return;
+        }
+    }
+
+    for child in node.children.borrow().iter() {
+        collect_json_ld(child, items);
+    }
+}
+
+/// Splits a JSON-LD document into its top level items, carrying the
+/// context of a `@graph` over to each of its members
+fn flatten_json_ld(value: Value) -> Vec<Value> {
+    match value {
+        Value::Array(values) => values.into_iter().flat_map(flatten_json_ld).collect(),
+        Value::Object(mut map) => match map.remove("@graph") {
+            Some(graph) => {
+                let context = map.get("@context").cloned();
+
+                flatten_json_ld(graph)
+                    .into_iter()
+                    .map(|mut item| {
+                        if let (Some(context), Some(object)) = (&context, item.as_object_mut()) {
+                            object.entry("@context").or_insert_with(|| context.clone());
+                        }
+                        item
+                    })
+                    .collect()
+            },
+            None => vec![Value::Object(map)],
+        },
+        _ => Vec::new(),
+    }
+}
+
+impl StructuredDataFormat {
+    fn get_attr(attrs: &[html5ever::Attribute], name: &str) -> Option<String> {
+        attrs.iter()
+            .find(|attr| attr.name.local.as_ref().eq_ignore_ascii_case(name))
+            .map(|attr| attr.value.trim().to_string())
+    }
+
+    /// Whether the element starts a new item
+    fn is_scope(&self, attrs: &[html5ever::Attribute]) -> bool {
+        match self {
+            StructuredDataFormat::Microdata => Self::get_attr(attrs, "itemscope").is_some(),
+            StructuredDataFormat::Rdfa => Self::get_attr(attrs, "typeof").is_some(),
+            StructuredDataFormat::JsonLd => false,
+        }
+    }
+
+    fn get_types(&self, attrs: &[html5ever::Attribute]) -> Vec<String> {
+        let types = match self {
+            StructuredDataFormat::Microdata => Self::get_attr(attrs, "itemtype"),
+            StructuredDataFormat::Rdfa => Self::get_attr(attrs, "typeof"),
+            StructuredDataFormat::JsonLd => None,
+        };
+
+        types
+            .map(|types| types.split_whitespace().map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    fn get_property_names(&self, attrs: &[html5ever::Attribute]) -> Vec<String> {
+        let names = match self {
+            StructuredDataFormat::Microdata => Self::get_attr(attrs, "itemprop"),
+            StructuredDataFormat::Rdfa => Self::get_attr(attrs, "property"),
+            StructuredDataFormat::JsonLd => None,
+        };
+
+        names
+            .map(|names| names.split_whitespace().map(String::from).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Finds top level microdata or RDFa items, those that aren't themselves
+/// the value of another item's property
+fn collect_items(node: &Handle, format: &StructuredDataFormat, items: &mut Vec<Value>) {
+    if let NodeData::Element { ref attrs, .. } = node.data {
+        let attrs = attrs.borrow();
+
+        if format.is_scope(&attrs) && format.get_property_names(&attrs).is_empty() {
+            items.push(build_item(node, format));
+        }
+    }
+
+    for child in node.children.borrow().iter() {
+        collect_items(child, format, items);
+    }
+}
+
+fn build_item(node: &Handle, format: &StructuredDataFormat) -> Value {
+    let mut item: Map<String, Value> = Map::new();
+
+    if let NodeData::Element { ref attrs, .. } = node.data {
+        let attrs = attrs.borrow();
+        let types = format.get_types(&attrs);
+
+        match types.len() {
+            0 => {},
+            1 => { item.insert("@type".to_string(), Value::String(types[0].clone())); },
+            _ => { item.insert("@type".to_string(), types.into_iter().map(Value::String).collect()); },
+        }
+
+        let id = match format {
+            StructuredDataFormat::Microdata => StructuredDataFormat::get_attr(&attrs, "itemid"),
+            _ => StructuredDataFormat::get_attr(&attrs, "resource"),
+        };
+
+        if let Some(id) = id {
+            item.insert("@id".to_string(), Value::String(id));
+        }
+    }
+
+    for child in node.children.borrow().iter() {
+        collect_properties(child, format, &mut item);
+    }
+
+    Value::Object(item)
+}
+
+fn collect_properties(node: &Handle, format: &StructuredDataFormat, item: &mut Map<String, Value>) {
+    if let NodeData::Element { ref name, ref attrs, .. } = node.data {
+        let (names, is_scope) = {
+            let attrs = attrs.borrow();
+            (format.get_property_names(&attrs), format.is_scope(&attrs))
+        };
+
+        if !names.is_empty() {
+            let value = if is_scope {
+                build_item(node, format)
+            } else {
+                Value::String(get_property_value(node, name.local.as_ref(), format))
+            };
+
+            for name in names {
+                match item.remove(&name) {
+                    Some(Value::Array(mut values)) => {
+                        values.push(value.clone());
+                        item.insert(name, Value::Array(values));
+                    },
+                    Some(existing) => {
+                        item.insert(name, Value::Array(vec![existing, value.clone()]));
+                    },
+                    None => {
+                        item.insert(name, value.clone());
+                    },
+                }
+            }
+        }
+
+        // Properties of a nested item belong to that item
+        if is_scope {
+            return;
+        }
+    }
+
+    for child in node.children.borrow().iter() {
+        collect_properties(child, format, item);
+    }
+}
+
+/// The value of a property element, following the microdata rules for
+/// which attribute holds it. RDFa prefers an explicit `content` attribute.
+fn get_property_value(node: &Handle, tag: &str, format: &StructuredDataFormat) -> String {
+    let attrs = match node.data {
+        NodeData::Element { ref attrs, .. } => attrs.borrow(),
+        _ => return String::new(),
+    };
+
+    if *format == StructuredDataFormat::Rdfa {
+        if let Some(content) = StructuredDataFormat::get_attr(&attrs, "content") {
+            return content;
+        }
+    }
+
+    let attribute = match tag {
+        "meta" => Some("content"),
+        "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => Some("src"),
+        "a" | "area" | "link" => Some("href"),
+        "object" => Some("data"),
+        "data" | "meter" => Some("value"),
+        "time" => Some("datetime"),
+        _ => None,
+    };
+
+    attribute
+        .and_then(|attribute| StructuredDataFormat::get_attr(&attrs, attribute))
+        .unwrap_or_else(|| get_text(node))
+}
+
+fn get_raw_text(node: &Handle) -> String {
+    node.children
+        .borrow()
+        .iter()
+        .filter_map(|child| match child.data {
+            NodeData::Text { ref contents } => Some(contents.borrow().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Maps an item onto a model's fields, directly by name or through a common
+/// schema.org alias. Returns `None` unless every required field is present.
+fn match_model(model: &Model, item: &Value) -> Option<ModelMatch> {
+    let schema: Value = serde_json::from_str(&model.json_schema).ok()?;
+    let properties = schema.get("properties")?.as_object()?;
+    let required: Vec<&str> = schema.get("required")
+        .and_then(|required| required.as_array())
+        .map(|required| required.iter().filter_map(|field| field.as_str()).collect())
+        .unwrap_or_default();
+
+    let mut data: Map<String, Value> = Map::new();
+
+    for field in properties.keys() {
+        let aliases = PROPERTY_ALIASES
+            .iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, aliases)| *aliases)
+            .unwrap_or(&[]);
+
+        let value = std::iter::once(field.as_str())
+            .chain(aliases.iter().cloned())
+            .find_map(|path| get_item_value(item, path));
+
+        if let Some(value) = value {
+            data.insert(field.clone(), value);
+        }
+    }
+
+    if required.iter().any(|field| !data.contains_key(*field)) {
+        return None;
+    }
+
+    Some(ModelMatch {
+        model_id: model.id.clone(),
+        model_name: model.name.clone(),
+        confidence: data.len() as f64 / properties.len().max(1) as f64,
+        data: Value::Object(data),
+    })
+}
+
+/// Looks up a dotted path in an item. Nested items stand for their `name`
+/// and lists of values are kept as lists.
+fn get_item_value(item: &Value, path: &str) -> Option<Value> {
+    let mut current = item;
+
+    for key in path.split('.') {
+        current = match current {
+            Value::Array(values) => values.first()?.get(key)?,
+            _ => current.get(key)?,
+        };
+    }
+
+    match current {
+        Value::Object(object) => object.get("name").or_else(|| object.get("@id")).cloned(),
+        Value::Null => None,
+        Value::String(value) if value.is_empty() => None,
+        value => Some(value.clone()),
+    }
+}
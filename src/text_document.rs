@@ -0,0 +1,103 @@
+use xmltree::{Element, XMLNode};
+
+/// Name of the element wrapping a plain text document
+pub const TEXT_ROOT_ELEMENT: &str = "text";
+
+/// Name of the element grouping consecutive non-blank lines
+pub const TEXT_BLOCK_ELEMENT: &str = "block";
+
+/// Name of the element holding the tokens of a single line
+pub const TEXT_LINE_ELEMENT: &str = "line";
+
+/// Maps plain text such as logs, command output or fixed-width reports onto
+/// the element tree used by `DocumentNode`. Blank lines separate blocks, each
+/// block holds its lines, and each line holds its whitespace separated
+/// tokens. Tokens are named after their kind (`number`, `word`, `symbol` or
+/// `token`) so that the feature paths of a profile describe the shape of a
+/// line, and carry the column they start at so aligned columns can be told
+/// apart.
+pub fn text_to_element(data: &str) -> Element {
+    let mut root = Element::new(TEXT_ROOT_ELEMENT);
+    let mut block: Option<Element> = None;
+
+    for (index, line) in data.lines().enumerate() {
+        let line_number = index + 1;
+
+        if line.trim().is_empty() {
+            if let Some(finished) = block.take() {
+                root.children.push(XMLNode::Element(finished));
+            }
+            continue;
+        }
+
+        let current = block.get_or_insert_with(|| {
+            let mut element = Element::new(TEXT_BLOCK_ELEMENT);
+            element.attributes.insert("line".to_string(), line_number.to_string());
+            element
+        });
+
+        current.children.push(XMLNode::Element(line_to_element(line, line_number)));
+    }
+
+    if let Some(finished) = block.take() {
+        root.children.push(XMLNode::Element(finished));
+    }
+
+    root
+}
+
+fn line_to_element(line: &str, line_number: usize) -> Element {
+    let mut element = Element::new(TEXT_LINE_ELEMENT);
+    let indent = line.chars().take_while(|c| c.is_whitespace()).count();
+
+    element.attributes.insert("number".to_string(), line_number.to_string());
+    element.attributes.insert("indent".to_string(), indent.to_string());
+
+    for (column, token) in get_tokens(line) {
+        let mut token_element = Element::new(get_token_kind(token));
+        token_element.attributes.insert("column".to_string(), column.to_string());
+        token_element.children.push(XMLNode::Text(token.to_string()));
+
+        element.children.push(XMLNode::Element(token_element));
+    }
+
+    element
+}
+
+/// Splits a line on whitespace, returning each token with the character
+/// column it starts at
+fn get_tokens(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start: Option<(usize, usize)> = None;
+
+    for (column, (offset, c)) in line.char_indices().enumerate() {
+        if c.is_whitespace() {
+            if let Some((token_column, token_offset)) = start.take() {
+                tokens.push((token_column, &line[token_offset..offset]));
+            }
+        } else if start.is_none() {
+            start = Some((column, offset));
+        }
+    }
+
+    if let Some((token_column, token_offset)) = start {
+        tokens.push((token_column, &line[token_offset..]));
+    }
+
+    tokens
+}
+
+fn get_token_kind(token: &str) -> &'static str {
+    let has_digit = token.chars().any(|c| c.is_ascii_digit());
+
+    if has_digit && token.chars().all(|c| c.is_ascii_digit() || "+-.,:/%".contains(c)) {
+        "number"
+    } else if token.chars().all(|c| c.is_alphabetic() || c == '\'' || c == '-')
+        && token.chars().any(|c| c.is_alphabetic()) {
+        "word"
+    } else if !token.chars().any(|c| c.is_alphanumeric()) {
+        "symbol"
+    } else {
+        "token"
+    }
+}
@@ -145,21 +145,34 @@ pub struct HashTransformation {
     pub infix: String,
 }
 
+impl Default for HashTransformation {
+    fn default() -> Self {
+        HashTransformation {
+            id: ID::new(),
+            description: String::from("Determines the set of input strings from a node to use in identity hash calculation"),
+            runtime: Runtime::QuickJS,
+            infix: String::from("let hasherItems = Object.keys(fields).sort()"),
+        }
+    }
+}
+
 impl HashTransformation {
     fn prefix(&self, fields: HashMap<String, String>) -> String {
         match self.runtime {
             Runtime::QuickJS => {
-                let fields_js: Vec<String> = fields
+                let fields_js: serde_json::Map<String, Value> = fields
                     .into_iter()
                     .map(|(key, value)| {
                         if key == "text" {
-                            format!("'{}': '<omitted>'", key)
+                            (key, Value::String(String::from("<omitted>")))
                         } else {
-                            format!("'{}': '{}'", key, value)
+                            (key, Value::String(value))
                         }
                     })
                     .collect();
-                format!("let fields = {{ {} }};", fields_js.join(", "))
+
+                // JSON is valid JavaScript, so this escapes quotes and newlines in values
+                format!("let fields = {};", Value::Object(fields_js))
             },
             _ => panic!("Unexpected runtime: {:?}", self.runtime),
         }
@@ -219,16 +232,46 @@ pub struct XMLElementTransformation {
     pub infix: String,
 }
 
+impl Default for XMLElementTransformation {
+    fn default() -> Self {
+        XMLElementTransformation {
+            id: ID::new(),
+            description: String::from("XML element transformation applied during document preprocessing that blacklists certain elements or attributes to reduce document size and improve interpretation."),
+            runtime: Runtime::QuickJS,
+            infix: String::from(r#"
+const BLACKLISTED_ATTRIBUTES = {style:1, bgcolor:1, border:1, cellpadding:1, cellspacing:1, width:1, height:1, rows:1, cols:1, wrap:1, "aria-hidden":1, size:1, op:1, lang:1, colspan:1, rel:1};
+const BLACKLISTED_ELEMENTS = {script:1,meta:1,link:1,iframe:1,svg:1,style:1,noscript:1};
+if (BLACKLISTED_ELEMENTS[element]) element = null;
+attributes = Object.keys(attributes)
+  .filter(item => !BLACKLISTED_ATTRIBUTES[item])
+  .reduce((acc, key) => {
+      acc[key] = attributes[key];
+      return acc;
+  }, {});
+"#),
+        }
+    }
+}
+
 impl XMLElementTransformation {
+    pub fn passthrough() -> Self {
+        XMLElementTransformation {
+            id: ID::new(),
+            description: String::from("XML element transformation that leaves every element and attribute in place."),
+            runtime: Runtime::QuickJS,
+            infix: String::new(),
+        }
+    }
+
     fn prefix(&self, element: String, attributes: HashMap<String, String>) -> String {
-        let element_code = format!("let element = '{}';", element);
+        let element_code = format!("let element = {};", Value::String(element));
 
         let attributes_code = {
-            let attributes_list: Vec<String> = attributes
+            let attributes_map: serde_json::Map<String, Value> = attributes
                 .into_iter()
-                .map(|(key, value)| format!("'{}': '{}'", key, value))
+                .map(|(key, value)| (key, Value::String(value)))
                 .collect();
-            format!("let attributes = {{ {} }};", attributes_list.join(", "))
+            format!("let attributes = {};", Value::Object(attributes_map))
         };
 
         format!("{}\n{}", element_code, attributes_code)
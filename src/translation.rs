@@ -7,7 +7,7 @@ use crate::organization::organize;
 use crate::provider::Provider;
 use crate::traverse::{build_document_from_nodeset};
 
-pub async fn translate<P: Provider>(
+pub async fn translate<P: Provider + ?Sized>(
     provider: Arc<P>,
     nodeset: NodeSet,
     options: &Option<Options>,
@@ -18,7 +18,7 @@ pub async fn translate<P: Provider>(
     unimplemented!()
 }
 
-pub async fn translate_nodeset<P: Provider>(
+pub async fn translate_nodeset<P: Provider + ?Sized>(
     provider: Arc<P>,
     nodeset: NodeSet,
     options: &Option<Options>,
@@ -29,7 +29,7 @@ pub async fn translate_nodeset<P: Provider>(
     translate(Arc::clone(&provider), nodeset, options, json_schema).await
 }
 
-pub async fn translate_text_to_nodeset<P: Provider>(
+pub async fn translate_text_to_nodeset<P: Provider + ?Sized>(
     provider: Arc<P>,
     text: String,
     options: &Option<Options>,
@@ -43,7 +43,7 @@ pub async fn translate_text_to_nodeset<P: Provider>(
     translate_nodeset(Arc::clone(&provider), nodeset, options, json_schema).await
 }
 
-pub async fn translate_text_to_document<P: Provider>(
+pub async fn translate_text_to_document<P: Provider + ?Sized>(
     provider: Arc<P>,
     text: String,
     options: &Option<Options>,
@@ -61,7 +61,7 @@ pub async fn translate_text_to_document<P: Provider>(
     )
 }
 
-pub async fn translate_text<P: Provider>(
+pub async fn translate_text<P: Provider + ?Sized>(
     provider: Arc<P>,
     text: String,
     options: &Option<Options>,
@@ -81,7 +81,7 @@ pub async fn translate_text<P: Provider>(
     Ok(document.to_string())
 }
 
-pub async fn translate_document_to_nodeset<P: Provider>(
+pub async fn translate_document_to_nodeset<P: Provider + ?Sized>(
     provider: Arc<P>,
     document: Document,
     options: &Option<Options>,
@@ -94,7 +94,7 @@ pub async fn translate_document_to_nodeset<P: Provider>(
     translate_nodeset(Arc::clone(&provider), nodeset, options, json_schema).await
 }
 
-pub async fn translate_document<P: Provider>(
+pub async fn translate_document<P: Provider + ?Sized>(
     provider: Arc<P>,
     document: Document,
     options: &Option<Options>,
@@ -117,7 +117,7 @@ pub async fn translate_document<P: Provider>(
     )
 }
 
-pub async fn translate_document_to_text<P: Provider>(
+pub async fn translate_document_to_text<P: Provider + ?Sized>(
     provider: Arc<P>,
     document: Document,
     options: &Option<Options>,
@@ -137,7 +137,7 @@ pub async fn translate_document_to_text<P: Provider>(
     Ok(document.to_string())
 }
 
-pub async fn translate_file_to_nodeset<P: Provider>(
+pub async fn translate_file_to_nodeset<P: Provider + ?Sized>(
     provider: Arc<P>,
     path: &str,
     options: &Option<Options>,
@@ -146,15 +146,16 @@ pub async fn translate_file_to_nodeset<P: Provider>(
     log::trace!("In translate_file_to_nodeset");
     log::debug!("file path: {}", path);
 
-    let text = get_file_as_text(path).map_err(|err| {
-        log::error!("Failed to get file as text: {:?}", err);
+    let bytes = get_file_as_bytes(path).map_err(|err| {
+        log::error!("Failed to get file as bytes: {:?}", err);
         Errors::FileInputError
     })?;
+    let document = Document::from_bytes(&bytes, options)?;
 
-    translate_text_to_nodeset(Arc::clone(&provider), text, options, json_schema).await
+    translate_document_to_nodeset(Arc::clone(&provider), document, options, json_schema).await
 }
 
-pub async fn translate_file_to_document<P: Provider>(
+pub async fn translate_file_to_document<P: Provider + ?Sized>(
     provider: Arc<P>,
     path: &str,
     options: &Option<Options>,
@@ -172,7 +173,7 @@ pub async fn translate_file_to_document<P: Provider>(
     )
 }
 
-pub async fn translate_file_to_text<P: Provider>(
+pub async fn translate_file_to_text<P: Provider + ?Sized>(
     provider: Arc<P>,
     path: &str,
     options: &Option<Options>,
@@ -192,7 +193,7 @@ pub async fn translate_file_to_text<P: Provider>(
     Ok(document.to_string())
 }
 
-pub async fn translate_file<P: Provider>(
+pub async fn translate_file<P: Provider + ?Sized>(
     provider: Arc<P>,
     path: &str,
     options: &Option<Options>,
@@ -9,6 +9,7 @@ use crate::document_node::DocumentNode;
 use crate::graph_node::{Graph, GraphNode};
 use crate::document::{Document, DocumentType};
 use crate::document_format::{DocumentFormat};
+use crate::document_builder::build_document;
 use crate::profile::Profile;
 use crate::provider::Provider;
 
@@ -24,16 +25,28 @@ pub fn traverse_with_context(
 ) -> Result<TraversalWithContext, Errors> {
     log::trace!("In traverse_with_context");
 
-    let document_root = document.get_document_node()?;
+    traverse_with_context_each(profile, document, &mut |_| {})
+}
+
+/// Traverses the document, handing each data node to `on_data_node` as soon
+/// as it has been built, parents before their children
+pub fn traverse_with_context_each(
+    profile: &Profile,
+    document: Document,
+    on_data_node: &mut dyn FnMut(&Arc<DataNode>),
+) -> Result<TraversalWithContext, Errors> {
+    log::trace!("In traverse_with_context_each");
+
+    let (document_root, structured_data) = document.get_document_node_with_structured_data()?;
     let document_root = Arc::new(RwLock::new(document_root.clone()));
 
-    let mut data_nodes: HashMap<ID, Arc<DataNode>> = HashMap::new();
+    let mut data_nodes: Vec<Arc<DataNode>> = Vec::new();
     let mut contexts: HashMap<ContextID, Arc<Context>> = HashMap::new();
     let mut context_ids: HashMap<ID, ContextID> = HashMap::new();
 
     fn recurse(
         document_node: Arc<RwLock<DocumentNode>>,
-        data_nodes: &mut HashMap<ID, Arc<DataNode>>,
+        on_data_node: &mut dyn FnMut(&Arc<DataNode>),
         parent_lineage: &Lineage,
         contexts: &mut HashMap<ContextID, Arc<Context>>,
         context_ids: &mut HashMap<ID, ContextID>,
@@ -46,9 +59,10 @@ pub fn traverse_with_context(
                 read_lock!(document_node).get_fields(),
                 read_lock!(document_node).get_description(),
                 parent_lineage,
+                read_lock!(document_node).get_provenance(),
             )
         );
-        data_nodes.insert(data_node.id.clone(), Arc::clone(&data_node));
+        on_data_node(&data_node);
 
         let graph_node = Arc::new(RwLock::new(
             GraphNode::from_data_node(
@@ -74,12 +88,12 @@ pub fn traverse_with_context(
 
         {
             let children: Vec<Arc<RwLock<GraphNode>>> = read_lock!(document_node)
-                .get_children(profile.xml_element_transformation.clone())
+                .get_children(profile.xml_element_transformation.clone(), profile.retain_comments)
                 .into_iter()
                 .map(|child| {
                     recurse(
                         Arc::new(RwLock::new(child)),
-                        data_nodes,
+                        on_data_node,
                         &data_node.lineage,
                         contexts,
                         context_ids,
@@ -98,7 +112,10 @@ pub fn traverse_with_context(
 
     let graph_root = recurse(
         Arc::clone(&document_root),
-        &mut data_nodes,
+        &mut |data_node| {
+            data_nodes.push(Arc::clone(data_node));
+            on_data_node(data_node);
+        },
         &Lineage::new(),
         &mut contexts,
         &mut context_ids,
@@ -108,13 +125,15 @@ pub fn traverse_with_context(
 
     let meta_context = MetaContext {
         context_ids,
-        graph_root,
+        graph_root: Arc::clone(&graph_root),
         document_root,
     };
 
     let traversal = TraversalWithContext {
         nodeset: NodeSet {
-            data_nodes: data_nodes.values().cloned().collect()
+            data_nodes,
+            graph_root: Some(graph_root),
+            structured_data,
         },
         meta_context,
         contexts,
@@ -123,12 +142,14 @@ pub fn traverse_with_context(
     Ok(traversal)
 }
 
-pub fn build_document_from_nodeset<P: Provider>(
+pub fn build_document_from_nodeset<P: Provider + ?Sized>(
     provider: Arc<P>,
     nodeset: NodeSet,
     document_format: &Option<DocumentFormat>,
 ) -> Result<Document, Errors> {
     log::trace!("In build_document_from_nodeset");
 
-    unimplemented!()
+    let document_format = document_format.clone().unwrap_or_default();
+
+    build_document(&nodeset, &document_format)
 }
@@ -3,9 +3,17 @@ use std::sync::{Arc};
 use crate::basis_graph::{BasisGraph};
 use crate::transformation::{Transformation};
 use crate::data_node::DataNode;
+use crate::graph_node::Graph;
+use crate::document::DocumentType;
+use crate::structured_data::StructuredData;
 
 pub struct NodeSet {
+    /// Data nodes in document order, each parent before its children
     pub data_nodes: Vec<Arc<DataNode>>,
+    /// Root of the parent/child structure of the data nodes
+    pub graph_root: Option<Graph>,
+    /// JSON-LD, microdata and RDFa items embedded in the document
+    pub structured_data: Vec<StructuredData>,
 }
 
 #[derive(Clone, Debug)]
@@ -26,11 +34,15 @@ pub enum Errors {
     UnexpectedError,
     UnexpectedOutputFormat,
     XmlParseError,
+    CsvParseError,
     BasisGraphBuildError(String),
     PathConversionError,
     SqliteDatabaseConnectionError,
+    SqliteDatabaseQueryError,
     YamlParseError,
     FetchUrlError(String),
+    /// Errors from the layers of a layered provider that failed to save
+    ProviderLayersError(Vec<Errors>),
 }
 
 #[derive(Clone, Debug)]
@@ -38,7 +50,22 @@ pub struct Options {
     pub analysis_mode: Option<AnalysisMode>,
     pub origin: Option<String>,
     pub date: Option<String>,
-    pub value_transformations: Option<Vec<Transformation>>
+    pub value_transformations: Option<Vec<Transformation>>,
+    /// Skips content sniffing and parses the document as this type
+    pub document_type: Option<DocumentType>,
+    /// Skips encoding detection and decodes raw input with this label
+    pub encoding: Option<String>,
+}
+
+impl Options {
+    /// Fills in the origin of a document fetched from a URL, keeping an
+    /// origin that was set explicitly
+    pub fn with_origin(options: &Option<Options>, origin: &str) -> Option<Options> {
+        let mut options = options.clone().unwrap_or_default();
+        options.origin.get_or_insert_with(|| origin.to_string());
+
+        Some(options)
+    }
 }
 
 impl Default for Options {
@@ -48,6 +75,8 @@ impl Default for Options {
             origin: None,
             date: None,
             value_transformations: None,
+            document_type: None,
+            encoding: None,
         }
     }
 }
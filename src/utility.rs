@@ -21,6 +21,13 @@ pub fn get_file_as_text(path: &str) -> Result<String, Errors> {
     Ok(text)
 }
 
+pub fn get_file_as_bytes(path: &str) -> Result<Vec<u8>, Errors> {
+    std::fs::read(path).map_err(|err| {
+        log::error!("Failed to read file: {}", err);
+        Errors::FileInputError
+    })
+}
+
 pub fn write_text_to_file(path: &str, text: &str) -> io::Result<()> {
     let mut file = File::create(path)?;
 
@@ -29,6 +36,23 @@ pub fn write_text_to_file(path: &str, text: &str) -> io::Result<()> {
     Ok(())
 }
 
+/// Writes to a temporary file next to the target and renames it over the
+/// target, so readers never see a partly written file
+pub fn replace_file_with_text(path: &str, text: &str) -> io::Result<()> {
+    let temporary_path = format!("{}.tmp", path);
+
+    {
+        let mut file = File::create(&temporary_path)?;
+
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+    }
+
+    std::fs::rename(&temporary_path, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temporary_path);
+    })
+}
+
 pub fn append_to_filename(path: &str, suffix: &str) -> Result<String, Errors> {
     let path = Path::new(path);
 
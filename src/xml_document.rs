@@ -0,0 +1,126 @@
+use xml::Encoding;
+use xml::reader::{ParserConfig, XmlEvent};
+use xmltree::{Element, XMLNode};
+
+use crate::prelude::*;
+
+/// Parses a genuine XML document (Atom, RSS, SOAP, sitemaps...) without going
+/// through the HTML parser. Element prefixes and namespace URIs are kept, as
+/// are prefixes on attribute names, CDATA sections, comments and processing
+/// instructions inside the root element. The data has already been decoded,
+/// so any encoding named in the XML declaration is ignored.
+pub fn parse_xml(data: &str) -> Result<Element, Errors> {
+    parse_markup(data, false)
+}
+
+/// Parses the XHTML serialized from an HTML DOM. Whitespace has already
+/// been normalized there, so whitespace-only text is kept as text nodes
+/// rather than dropped as indentation.
+pub fn parse_xhtml(data: &str) -> Result<Element, Errors> {
+    parse_markup(data, true)
+}
+
+fn parse_markup(data: &str, keep_whitespace: bool) -> Result<Element, Errors> {
+    let reader = ParserConfig::new()
+        .ignore_comments(false)
+        .trim_whitespace(false)
+        .cdata_to_characters(false)
+        .override_encoding(Some(Encoding::Utf8))
+        .ignore_invalid_encoding_declarations(true)
+        .create_reader(data.as_bytes());
+
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root: Option<Element> = None;
+
+    for event in reader {
+        let event = event.map_err(|err| {
+            log::error!("Could not parse XML: {}", err);
+            Errors::XmlParseError
+        })?;
+
+        match event {
+            XmlEvent::StartElement { name, attributes, namespace } => {
+                let mut element = Element::new(&name.local_name);
+                element.prefix = name.prefix.clone();
+                element.namespace = name.namespace.clone();
+                element.namespaces = Some(namespace);
+
+                for attribute in attributes {
+                    let attribute_name = match attribute.name.prefix {
+                        Some(prefix) => format!("{}:{}", prefix, attribute.name.local_name),
+                        None => attribute.name.local_name,
+                    };
+
+                    element.attributes.insert(attribute_name, attribute.value);
+                }
+
+                stack.push(element);
+            },
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().ok_or(Errors::XmlParseError)?;
+
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(XMLNode::Element(element)),
+                    None => root = Some(element),
+                }
+            },
+            XmlEvent::Characters(text) => {
+                if let Some(current) = stack.last_mut() {
+                    current.children.push(XMLNode::Text(text));
+                }
+            },
+            XmlEvent::Whitespace(text) if keep_whitespace => {
+                if let Some(current) = stack.last_mut() {
+                    current.children.push(XMLNode::Text(text));
+                }
+            },
+            XmlEvent::CData(text) => {
+                if let Some(current) = stack.last_mut() {
+                    current.children.push(XMLNode::CData(text));
+                }
+            },
+            XmlEvent::Comment(text) => {
+                if let Some(current) = stack.last_mut() {
+                    current.children.push(XMLNode::Comment(text));
+                }
+            },
+            XmlEvent::ProcessingInstruction { name, data } => {
+                match stack.last_mut() {
+                    Some(current) => {
+                        current.children.push(XMLNode::ProcessingInstruction(name, data));
+                    },
+                    None => {
+                        log::debug!("Ignoring processing instruction outside root element: {}", name);
+                    }
+                }
+            },
+            XmlEvent::StartDocument { .. } |
+            XmlEvent::EndDocument |
+            XmlEvent::Whitespace(_) => {}
+        }
+    }
+
+    root.ok_or(Errors::XmlParseError)
+}
+
+/// The element name as written in the document, including any prefix
+pub fn get_qualified_name(element: &Element) -> String {
+    match &element.prefix {
+        Some(prefix) => format!("{}:{}", prefix, element.name),
+        None => element.name.clone(),
+    }
+}
+
+/// Sets the element name from a possibly prefixed name
+pub fn set_qualified_name(element: &mut Element, qualified_name: &str) {
+    match qualified_name.split_once(':') {
+        Some((prefix, name)) => {
+            element.prefix = Some(prefix.to_string());
+            element.name = name.to_string();
+        },
+        None => {
+            element.prefix = None;
+            element.name = qualified_name.to_string();
+        }
+    }
+}